@@ -0,0 +1,165 @@
+// each pipeline stage on its own, over a synthetic image and a few of the bundled ones.
+// `cargo bench` keeps the results in target/criterion and reports the change since the last
+// run. to compare two versions:
+//     git checkout old && cargo bench -- --save-baseline old
+//     git checkout new && cargo bench -- --baseline old
+// a filter picks out stages or inputs, e.g. `cargo bench -- canny/toronto`
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use image::{DynamicImage, GrayImage, Luma};
+use line_to_equation::{config, img_to_line, parallel, Path, Pipeline};
+use std::hint::black_box;
+
+const BLUR_SIGMA: f32 = 1.4;
+const CANNY: (f32, f32) = (50.0, 100.0);
+const SIMPLIFY_EPSILON: f32 = 1.0;
+
+struct Input {
+    name: &'static str,
+    image: DynamicImage,
+    blurred: DynamicImage,
+    edges: DynamicImage, // sobel edges, what the default pipeline traces
+    paths: Vec<Path>,
+    selected: Vec<Path>,
+}
+
+// overlapping discs of different greys, so every edge has a known position and contrast
+fn synthetic(width: u32, height: u32) -> DynamicImage {
+    let discs: Vec<(f32, f32, f32, u8)> = (0..24)
+        .map(|i| {
+            let i = i as f32;
+            let x = (i * 0.37).fract() * width as f32;
+            let y = (i * 0.61).fract() * height as f32;
+            let r = 20.0 + (i * 0.23).fract() * height as f32 / 4.0;
+            (x, y, r, 40 + (i as u8 * 37) % 200)
+        })
+        .collect();
+    let img = GrayImage::from_fn(width, height, |x, y| {
+        let (x, y) = (x as f32, y as f32);
+        let grey = discs
+            .iter()
+            .rev()
+            .find(|(cx, cy, r, _)| (x - cx).powi(2) + (y - cy).powi(2) < r * r)
+            .map_or(0, |d| d.3);
+        Luma([grey])
+    });
+    DynamicImage::ImageLuma8(img)
+}
+
+fn inputs() -> Vec<Input> {
+    let files = [
+        ("shapes1", "images/shapes1.png"),
+        ("toronto", "images/toronto.png"),
+        ("big_apple", "images/big_apple.jpg"),
+    ];
+    let mut images = vec![("synthetic", synthetic(640, 480))];
+    for (name, file) in files {
+        images.push((name, img_to_line::get_image(file).unwrap()));
+    }
+    let pipeline = Pipeline::new();
+    images
+        .into_iter()
+        .map(|(name, image)| {
+            let blurred = parallel::gaussian_blur_sigma(&image, BLUR_SIGMA, 0);
+            let (_, edges) = pipeline.detect_edges(&image).unwrap();
+            let paths = pipeline.trace_paths(&edges).unwrap();
+            let selected = pipeline.filter_paths(&paths, &blurred).unwrap();
+            Input {
+                name,
+                image,
+                blurred,
+                edges,
+                paths,
+                selected,
+            }
+        })
+        .collect()
+}
+
+fn pixels(img: &DynamicImage) -> Throughput {
+    Throughput::Elements(img.width() as u64 * img.height() as u64)
+}
+
+fn stages(c: &mut Criterion) {
+    let inputs = inputs();
+    let pipeline = Pipeline::new();
+
+    let mut group = c.benchmark_group("blur");
+    for input in inputs.iter() {
+        group.throughput(pixels(&input.image));
+        group.bench_with_input(BenchmarkId::from_parameter(input.name), input, |b, i| {
+            b.iter(|| parallel::gaussian_blur_sigma(black_box(&i.image), BLUR_SIGMA, 0))
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("sobel");
+    for input in inputs.iter() {
+        group.throughput(pixels(&input.image));
+        group.bench_with_input(BenchmarkId::from_parameter(input.name), input, |b, i| {
+            b.iter(|| parallel::sobel_threshold(black_box(&i.image), 128, false, 0))
+        });
+    }
+    group.finish();
+
+    // gradient, non-maximum suppression, double threshold and hysteresis are one fused pass
+    let mut group = c.benchmark_group("canny");
+    for input in inputs.iter() {
+        group.throughput(pixels(&input.blurred));
+        group.bench_with_input(BenchmarkId::from_parameter(input.name), input, |b, i| {
+            b.iter(|| parallel::canny(black_box(&i.blurred), CANNY.0, CANNY.1, 0).unwrap())
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("trace");
+    for input in inputs.iter() {
+        group.throughput(pixels(&input.edges));
+        group.bench_with_input(BenchmarkId::from_parameter(input.name), input, |b, i| {
+            b.iter(|| pipeline.trace_paths(black_box(&i.edges)).unwrap())
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("simplify");
+    for input in inputs.iter() {
+        let points = input.paths.iter().map(|p| p.len() as u64).sum();
+        group.throughput(Throughput::Elements(points));
+        group.bench_with_input(BenchmarkId::from_parameter(input.name), input, |b, i| {
+            b.iter(|| {
+                i.paths
+                    .iter()
+                    .map(|p| img_to_line::simplify(black_box(p), SIMPLIFY_EPSILON))
+                    .collect::<Vec<_>>()
+            })
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("fit");
+    for input in inputs.iter() {
+        group.throughput(Throughput::Elements(input.selected.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(input.name), input, |b, i| {
+            b.iter(|| pipeline.fit_curves(black_box(&i.selected)))
+        });
+    }
+    group.finish();
+
+    // the whole thing with the photo preset, to catch slowdowns between the stages
+    let photo = config::preset("photo").unwrap();
+    let mut group = c.benchmark_group("pipeline");
+    for input in inputs.iter() {
+        group.throughput(pixels(&input.image));
+        group.bench_with_input(BenchmarkId::from_parameter(input.name), input, |b, i| {
+            b.iter(|| photo.run(black_box(i.image.clone())).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    // big_apple takes around a second per stage, the default 100 samples would take minutes
+    config = Criterion::default().sample_size(10);
+    targets = stages
+}
+criterion_main!(benches);
@@ -0,0 +1,257 @@
+use crate::error::{Error, Result};
+use crate::model::CurveModel;
+use serde::{Deserialize, Serialize};
+
+// what the beam does between the end of one curve and the start of the next, and from the last
+// curve back to the first when the frame loops
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Blanking {
+    Jump,           // go straight to the next curve
+    Retrace(usize), // move in a straight line over n samples
+    Dwell(usize),   // jump, then hold the start point for n samples so the beam settles
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AudioOptions {
+    pub sample_rate: u32,
+    pub frame_rate: f32, // how many times per second the whole drawing is traced
+    pub loops: u32,      // number of frames in the file
+    pub blanking: Blanking,
+}
+
+impl Default for AudioOptions {
+    fn default() -> Self {
+        AudioOptions {
+            sample_rate: 48000,
+            frame_rate: 50.0,
+            loops: 250, // 5 seconds
+            blanking: Blanking::Retrace(4),
+        }
+    }
+}
+
+fn length(points: &[(f32, f32)]) -> f32 {
+    points
+        .windows(2)
+        .map(|w| ((w[1].0 - w[0].0).powi(2) + (w[1].1 - w[0].1).powi(2)).sqrt())
+        .sum()
+}
+
+// a config file can hold anything, and a frame rate of 0 would want endless samples
+fn check(options: &AudioOptions) -> Result<()> {
+    if options.sample_rate == 0 {
+        return Err(Error::InvalidParameter(
+            "audio sample rate must be above 0".to_string(),
+        ));
+    }
+    if !(options.frame_rate > 0.0 && options.frame_rate.is_finite()) {
+        return Err(Error::InvalidParameter(format!(
+            "audio frame rate {} must be above 0",
+            options.frame_rate
+        )));
+    }
+    Ok(())
+}
+
+// one frame of (left, right) samples in [-1, 1], samples are shared out by curve length
+// so the beam moves at roughly the same speed everywhere and the brightness is even
+// the samples that take the beam from the last point to next, between two curves
+fn blank_to(points: &mut Vec<(f32, f32)>, next: (f32, f32), blanking: Blanking) {
+    let Some(&prev) = points.last() else {
+        return;
+    };
+    match blanking {
+        Blanking::Jump => {}
+        Blanking::Retrace(n) => {
+            for i in 1..=n {
+                let s = i as f32 / (n + 1) as f32;
+                points.push((
+                    prev.0 + (next.0 - prev.0) * s,
+                    prev.1 + (next.1 - prev.1) * s,
+                ));
+            }
+        }
+        Blanking::Dwell(n) => points.extend(std::iter::repeat_n(next, n)),
+    }
+}
+
+pub fn frame(curves: &[impl CurveModel], options: &AudioOptions) -> Result<Vec<(f32, f32)>> {
+    check(options)?;
+    if curves.is_empty() {
+        return Ok(vec![]);
+    }
+    let per_frame = (options.sample_rate as f32 / options.frame_rate) as usize;
+    let blank = match options.blanking {
+        Blanking::Jump => 0,
+        Blanking::Retrace(n) | Blanking::Dwell(n) => n,
+    };
+    let budget = per_frame
+        .saturating_sub(blank * curves.len())
+        .max(2 * curves.len());
+
+    let lengths: Vec<f32> = curves.iter().map(|c| length(&c.sample(256))).collect();
+    let total: f32 = lengths.iter().sum::<f32>().max(f32::EPSILON);
+
+    let mut points: Vec<(f32, f32)> = vec![];
+    for (curve, len) in curves.iter().zip(lengths.iter()) {
+        let n = ((budget as f32 * len / total) as usize).max(2);
+        let samples = curve.sample(n);
+        blank_to(&mut points, samples[0], options.blanking);
+        points.extend(samples);
+    }
+    // the frame loops, so the beam also has to get from the end of the last curve back to the start
+    if let Some(&first) = points.first() {
+        blank_to(&mut points, first, options.blanking);
+    }
+
+    // centre on the drawing and scale the longest side to [-1, 1], y points up on a scope
+    let (min_x, max_x, min_y, max_y) = points.iter().fold(
+        (f32::MAX, f32::MIN, f32::MAX, f32::MIN),
+        |(a, b, c, d), &(x, y)| (a.min(x), b.max(x), c.min(y), d.max(y)),
+    );
+    let (cx, cy) = ((min_x + max_x) / 2.0, (min_y + max_y) / 2.0);
+    let half = ((max_x - min_x).max(max_y - min_y) / 2.0).max(f32::EPSILON);
+    Ok(points
+        .iter()
+        .map(|&(x, y)| ((x - cx) / half, -(y - cy) / half))
+        .collect())
+}
+
+pub fn render(curves: &[impl CurveModel], options: &AudioOptions) -> Result<Vec<(f32, f32)>> {
+    let frame = frame(curves, options)?;
+    let mut samples = Vec::with_capacity(frame.len() * options.loops as usize);
+    for _ in 0..options.loops {
+        samples.extend_from_slice(&frame);
+    }
+    Ok(samples)
+}
+
+// 16 bit stereo PCM, left channel is x and right channel is y
+pub fn encode_wav(samples: &[(f32, f32)], sample_rate: u32) -> Vec<u8> {
+    let data_len = samples.len() as u32 * 4;
+    let mut wav = Vec::with_capacity(44 + data_len as usize);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+    wav.extend_from_slice(b"WAVE");
+    wav.extend_from_slice(b"fmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    wav.extend_from_slice(&2u16.to_le_bytes()); // channels
+    wav.extend_from_slice(&sample_rate.to_le_bytes());
+    wav.extend_from_slice(&(sample_rate * 4).to_le_bytes()); // byte rate
+    wav.extend_from_slice(&4u16.to_le_bytes()); // block align
+    wav.extend_from_slice(&16u16.to_le_bytes()); // bits per sample
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_len.to_le_bytes());
+    for &(l, r) in samples.iter() {
+        wav.extend_from_slice(&((l.clamp(-1.0, 1.0) * 32767.0).round() as i16).to_le_bytes());
+        wav.extend_from_slice(&((r.clamp(-1.0, 1.0) * 32767.0).round() as i16).to_le_bytes());
+    }
+    wav
+}
+
+pub fn save_wav(
+    file: impl AsRef<std::path::Path>,
+    curves: &[impl CurveModel],
+    options: &AudioOptions,
+) -> Result<()> {
+    let samples = render(curves, options)?;
+    std::fs::write(&file, encode_wav(&samples, options.sample_rate)).map_err(Error::io(&file))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fourier::FourierSeries;
+
+    fn decode_wav(wav: &[u8]) -> (u32, Vec<(f32, f32)>) {
+        assert_eq!(&wav[0..4], b"RIFF");
+        assert_eq!(&wav[8..16], b"WAVEfmt ");
+        assert_eq!(u16::from_le_bytes([wav[22], wav[23]]), 2);
+        let sample_rate = u32::from_le_bytes(wav[24..28].try_into().unwrap());
+        assert_eq!(&wav[36..40], b"data");
+        let len = u32::from_le_bytes(wav[40..44].try_into().unwrap()) as usize;
+        let samples = wav[44..44 + len]
+            .chunks(4)
+            .map(|c| {
+                (
+                    i16::from_le_bytes([c[0], c[1]]) as f32 / 32767.0,
+                    i16::from_le_bytes([c[2], c[3]]) as f32 / 32767.0,
+                )
+            })
+            .collect();
+        (sample_rate, samples)
+    }
+
+    fn square() -> FourierSeries {
+        let mut path = vec![];
+        path.extend((0..20).map(|i| (i, 0)));
+        path.extend((0..20).map(|i| (20, i)));
+        path.extend((0..20).map(|i| (20 - i, 20)));
+        path.extend((0..20).map(|i| (0, 20 - i)));
+        FourierSeries::fit(&path)
+    }
+
+    #[test]
+    fn test_wav_round_trip() {
+        let curves = vec![square(), square()];
+        let options = AudioOptions {
+            sample_rate: 8000,
+            frame_rate: 100.0,
+            loops: 3,
+            blanking: Blanking::Dwell(5),
+        };
+        let samples = render(&curves, &options).unwrap();
+        assert_eq!(samples.len() % 3, 0);
+        assert!(samples.len() / 3 <= 80);
+
+        let (sample_rate, decoded) = decode_wav(&encode_wav(&samples, options.sample_rate));
+        assert_eq!(sample_rate, 8000);
+        assert_eq!(decoded.len(), samples.len());
+        for (a, b) in samples.iter().zip(decoded.iter()) {
+            assert!((a.0 - b.0).abs() < 1e-4 && (a.1 - b.1).abs() < 1e-4);
+        }
+        assert!(decoded
+            .iter()
+            .all(|&(l, r)| (-1.0..=1.0).contains(&l) && (-1.0..=1.0).contains(&r)));
+        assert!(decoded.iter().any(|&(l, _)| !(-0.99..=0.99).contains(&l)));
+    }
+
+    #[test]
+    fn test_retrace_is_continuous() {
+        let a = FourierSeries::fit(&(0..20).map(|i| (i, 0)).collect::<Vec<_>>());
+        let b = FourierSeries::fit(&(0..20).map(|i| (i, 100)).collect::<Vec<_>>());
+        let options = AudioOptions {
+            blanking: Blanking::Retrace(50),
+            ..Default::default()
+        };
+        let frame = frame(&[a, b], &options).unwrap();
+        let max_step = frame
+            .windows(2)
+            .map(|w| (w[1].1 - w[0].1).abs())
+            .fold(0.0, f32::max);
+        assert!(max_step < 0.1);
+        // and from the end of the frame back to its start, where the next loop begins
+        let wrap = frame[frame.len() - 1].1 - frame[0].1;
+        assert!(wrap.abs() < 0.1);
+    }
+
+    #[test]
+    fn test_rates_must_be_positive() {
+        for (sample_rate, frame_rate) in
+            [(48000, 0.0), (48000, -50.0), (48000, f32::NAN), (0, 50.0)]
+        {
+            let options = AudioOptions {
+                sample_rate,
+                frame_rate,
+                ..Default::default()
+            };
+            assert!(matches!(
+                render(&[square()], &options),
+                Err(Error::InvalidParameter(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,377 @@
+use crate::error::{Error, Result};
+use crate::model::CurveModel;
+use crate::pipeline::{Format, Pipeline, Stage};
+use crate::{metrics, parallel};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fmt::Write as _;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Instant;
+
+const IMAGE_EXTENSIONS: [&str; 8] = ["png", "jpg", "jpeg", "gif", "bmp", "tif", "tiff", "webp"];
+
+#[derive(Debug, Clone, Default)]
+pub struct BatchOptions {
+    pub jobs: usize, // images processed at once, 0 for one per core
+}
+
+// one row of the summary, the stage fields are None when the image failed before reaching them
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ImageReport {
+    pub input: PathBuf,
+    pub output: PathBuf,
+    pub error: Option<String>,
+    pub total_ms: f64,
+    pub edges_ms: Option<f64>,
+    pub trace_ms: Option<f64>,
+    pub filter_ms: Option<f64>,
+    pub fit_ms: Option<f64>,
+    pub export_ms: Option<f64>,
+    pub paths: usize,
+    pub curves: usize,
+    pub coefficients: usize, // parameters of the written models over all curves
+    pub hausdorff: Option<f32>,
+    pub chamfer: Option<f32>,
+    pub precision: Option<f32>,
+    pub recall: Option<f32>,
+    pub f1: Option<f32>,
+}
+
+fn is_image(file: &Path) -> bool {
+    file.is_file()
+        && file.extension().is_some_and(|ext| {
+            let ext = ext.to_string_lossy().to_lowercase();
+            IMAGE_EXTENSIONS.contains(&ext.as_str())
+        })
+}
+
+// every image directly inside a directory, or every image matching a glob like "scans/*.png"
+pub fn find_images(input: &str) -> Result<Vec<PathBuf>> {
+    let dir = Path::new(input);
+    let mut files: Vec<PathBuf> = if dir.is_dir() {
+        dir.read_dir()
+            .map_err(Error::io(dir))?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .collect()
+    } else {
+        glob::glob(input)
+            .map_err(|e| Error::InvalidParameter(format!("{input}: {e}")))?
+            .filter_map(|file| file.ok())
+            .collect()
+    };
+    files.retain(|file| is_image(file));
+    files.sort();
+    if files.is_empty() {
+        return Err(Error::EmptyInput(format!("no images found in {input}")));
+    }
+    Ok(files)
+}
+
+// a subfolder per image named after it, a.png and a.jpg end up in a and a_2
+fn output_dirs(dir: &Path, inputs: &[PathBuf]) -> Vec<PathBuf> {
+    let mut used = HashSet::new();
+    inputs
+        .iter()
+        .map(|input| {
+            let stem = input.file_stem().unwrap_or_default().to_string_lossy();
+            let mut name = stem.to_string();
+            let mut n = 1;
+            while !used.insert(name.clone()) {
+                n += 1;
+                name = format!("{stem}_{n}");
+            }
+            dir.join(name)
+        })
+        .collect()
+}
+
+fn process(pipeline: &Pipeline, input: &Path, output: &Path) -> Result<ImageReport> {
+    let mut export = pipeline.export.clone().unwrap_or_default();
+    export.dir = output.to_path_buf();
+    export.formats.retain(|f| *f != Format::Config); // written once for the whole batch
+
+    let img = crate::img_to_line::get_image(&input.to_string_lossy())?;
+    let mut result = pipeline.run_until(img, Stage::Fit)?;
+    let now = Instant::now();
+    pipeline.write(&mut result, &export)?;
+    result.timings.push((Stage::Export, now.elapsed()));
+
+    // the summary always has metrics, even when metrics.json was not asked for
+    let m = match result.metrics.take() {
+        Some(m) => m,
+        None => metrics::evaluate(
+            &result.edges,
+            pipeline.trace.colour,
+            &result.selected,
+            &result.models,
+            export.tolerance,
+        ),
+    };
+
+    let mut report = ImageReport {
+        paths: result.paths.len(),
+        curves: result.curves.len(),
+        coefficients: result.models.iter().map(|m| m.parameters()).sum(),
+        hausdorff: Some(m.hausdorff),
+        chamfer: Some(m.chamfer),
+        precision: Some(m.precision),
+        recall: Some(m.recall),
+        f1: Some(m.f1),
+        ..Default::default()
+    };
+    for (stage, elapsed) in result.timings {
+        let ms = Some(elapsed.as_secs_f64() * 1000.0);
+        match stage {
+            Stage::Edges => report.edges_ms = ms,
+            Stage::Trace => report.trace_ms = ms,
+            Stage::Filter => report.filter_ms = ms,
+            Stage::Fit => report.fit_ms = ms,
+            Stage::Export => report.export_ms = ms,
+        }
+    }
+    Ok(report)
+}
+
+fn panic_message(payload: Box<dyn std::any::Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(msg) => *msg,
+        Err(payload) => match payload.downcast::<&str>() {
+            Ok(msg) => msg.to_string(),
+            Err(_) => "panicked".to_string(),
+        },
+    }
+}
+
+// runs the pipeline on every input, writing into <export dir>/<image name>/. a failing image
+// gets its error in the report and the rest carry on. `done` is called as each image finishes,
+// the reports come back in the order of `inputs`
+pub fn run(
+    pipeline: &Pipeline,
+    inputs: &[PathBuf],
+    options: &BatchOptions,
+    done: impl Fn(&ImageReport) + Sync,
+) -> Vec<ImageReport> {
+    let dir = pipeline
+        .export
+        .as_ref()
+        .map(|e| e.dir.clone())
+        .unwrap_or_default();
+    let outputs = output_dirs(&dir, inputs);
+    let cores = parallel::thread_count(0);
+    let jobs = match options.jobs {
+        0 => cores,
+        n => n,
+    };
+    // images already run side by side, so each one only gets its share of the cores
+    let mut pipeline = pipeline.clone();
+    if pipeline.threads == 0 {
+        pipeline.threads = (cores / jobs).max(1);
+    }
+    let pipeline = &pipeline;
+
+    let next = AtomicUsize::new(0);
+    let reports = Mutex::new(vec![None; inputs.len()]);
+    thread::scope(|s| {
+        for _ in 0..jobs.min(inputs.len()) {
+            s.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                if i >= inputs.len() {
+                    break;
+                }
+                let now = Instant::now();
+                let (input, output) = (&inputs[i], &outputs[i]);
+                let result =
+                    panic::catch_unwind(AssertUnwindSafe(|| process(pipeline, input, output)));
+                let mut report = match result {
+                    Ok(Ok(report)) => report,
+                    Ok(Err(e)) => ImageReport {
+                        error: Some(e.to_string()),
+                        ..Default::default()
+                    },
+                    Err(payload) => ImageReport {
+                        error: Some(panic_message(payload)),
+                        ..Default::default()
+                    },
+                };
+                report.input = input.clone();
+                report.output = output.clone();
+                report.total_ms = now.elapsed().as_secs_f64() * 1000.0;
+                done(&report);
+                reports.lock().unwrap()[i] = Some(report);
+            });
+        }
+    });
+    reports
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .flatten()
+        .collect()
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn csv_number(value: Option<impl std::fmt::Display>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_default()
+}
+
+pub fn summary_csv(reports: &[ImageReport]) -> String {
+    let mut csv = String::from(
+        "input,output,error,total_ms,edges_ms,trace_ms,filter_ms,fit_ms,export_ms,\
+         paths,curves,coefficients,hausdorff,chamfer,precision,recall,f1\n",
+    );
+    for r in reports.iter() {
+        let ms = |ms: Option<f64>| csv_number(ms.map(|ms| format!("{ms:.2}")));
+        let _ = writeln!(
+            csv,
+            "{},{},{},{:.2},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            csv_field(&r.input.to_string_lossy()),
+            csv_field(&r.output.to_string_lossy()),
+            csv_field(r.error.as_deref().unwrap_or("")),
+            r.total_ms,
+            ms(r.edges_ms),
+            ms(r.trace_ms),
+            ms(r.filter_ms),
+            ms(r.fit_ms),
+            ms(r.export_ms),
+            r.paths,
+            r.curves,
+            r.coefficients,
+            csv_number(r.hausdorff.filter(|h| h.is_finite())),
+            csv_number(r.chamfer.filter(|c| c.is_finite())),
+            csv_number(r.precision),
+            csv_number(r.recall),
+            csv_number(r.f1),
+        );
+    }
+    csv
+}
+
+pub fn summary_json(reports: &[ImageReport]) -> String {
+    // non-finite distances (nothing fitted) come out as null
+    serde_json::to_string_pretty(reports).unwrap()
+}
+
+// summary.csv and summary.json in `dir`
+pub fn save_summary(dir: impl AsRef<Path>, reports: &[ImageReport]) -> Result<Vec<PathBuf>> {
+    let dir = dir.as_ref();
+    std::fs::create_dir_all(dir).map_err(Error::io(dir))?;
+    let files = [
+        (dir.join("summary.csv"), summary_csv(reports)),
+        (dir.join("summary.json"), summary_json(reports)),
+    ];
+    files
+        .into_iter()
+        .map(|(file, text)| {
+            std::fs::write(&file, text)
+                .map_err(Error::io(&file))
+                .map(|_| file)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{Model, ModelKind, SelectOptions};
+    use crate::pipeline::{EdgeDetector, EdgeOptions, ExportOptions, FitOptions, TraceOptions};
+
+    #[test]
+    fn test_output_dirs_unique() {
+        let inputs = ["a/x.png", "b/x.png", "x.jpg", "y.png"].map(PathBuf::from);
+        let dirs = output_dirs(Path::new("out"), &inputs);
+        let names: Vec<_> = dirs.iter().map(|d| d.to_string_lossy()).collect();
+        assert_eq!(names, ["out/x", "out/x_2", "out/x_3", "out/y"]);
+    }
+
+    #[test]
+    fn test_batch_continues_past_failures() {
+        let dir = std::env::temp_dir().join(format!("batch_test_{}", std::process::id()));
+        let pipeline = Pipeline::new()
+            .edges(EdgeOptions {
+                detector: EdgeDetector::None,
+            })
+            .trace(TraceOptions {
+                colour: crate::img_to_line::BLACK,
+                ..Default::default()
+            })
+            .export(ExportOptions {
+                dir: dir.clone(),
+                formats: vec![Format::Txt],
+                ..Default::default()
+            });
+        let inputs =
+            ["images/line.png", "images/missing.png", "images/smile.png"].map(PathBuf::from);
+        let finished = AtomicUsize::new(0);
+        let options = BatchOptions { jobs: 2 };
+        let reports = run(&pipeline, &inputs, &options, |_| {
+            finished.fetch_add(1, Ordering::Relaxed);
+        });
+
+        assert_eq!(finished.into_inner(), 3);
+        assert_eq!(reports.len(), 3);
+        assert!(reports[0].error.is_none() && reports[2].error.is_none());
+        assert!(reports[1].error.as_ref().unwrap().contains("missing.png"));
+        assert!(reports[0].curves > 0 && reports[0].coefficients > reports[0].curves);
+        assert!(dir.join("line/equations.txt").exists());
+        assert!(dir.join("smile/equations.txt").exists());
+
+        let csv = summary_csv(&reports);
+        assert_eq!(csv.lines().count(), 4);
+        assert!(csv
+            .lines()
+            .nth(2)
+            .unwrap()
+            .starts_with("images/missing.png,"));
+        let json: serde_json::Value = serde_json::from_str(&summary_json(&reports)).unwrap();
+        assert_eq!(json.as_array().unwrap().len(), 3);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_coefficients_follow_models() {
+        let dir = std::env::temp_dir().join(format!("batch_models_{}", std::process::id()));
+        let pipeline = Pipeline::new()
+            .edges(EdgeOptions {
+                detector: EdgeDetector::None,
+            })
+            .trace(TraceOptions {
+                colour: crate::img_to_line::BLACK,
+                ..Default::default()
+            })
+            .fit(FitOptions {
+                select: Some(SelectOptions {
+                    candidates: vec![ModelKind::Bezier],
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
+            .export(ExportOptions {
+                dir: dir.clone(),
+                formats: vec![Format::Txt],
+                ..Default::default()
+            });
+        let inputs = [PathBuf::from("images/smile.png")];
+        let reports = run(&pipeline, &inputs, &BatchOptions { jobs: 1 }, |_| {});
+        assert!(reports[0].error.is_none());
+
+        let img = crate::img_to_line::get_image("images/smile.png").unwrap();
+        let result = pipeline.run_until(img, Stage::Fit).unwrap();
+        assert!(result.models.iter().all(|m| matches!(m, Model::Bezier(_))));
+        let beziers: usize = result.models.iter().map(|m| m.parameters()).sum();
+        let fourier: usize = result.curves.iter().map(|c| c.parameters()).sum();
+        assert_eq!(reports[0].coefficients, beziers);
+        assert_ne!(reports[0].coefficients, fourier);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
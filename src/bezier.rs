@@ -0,0 +1,423 @@
+use crate::img_to_line::Path;
+use crate::piecewise::{self, PiecewiseOptions};
+use serde::{Deserialize, Serialize};
+
+// Paths as a short run of cubic Bézier segments, after Schneider's "An algorithm for
+// automatically fitting digitized curves" (Graphics Gems, 1990). The path is split at its
+// corners, then each piece is fitted with one cubic and split at its worst point until every
+// point is within the tolerance. Splits inside a piece share their tangent, so smooth joints stay
+// smooth and only corners are sharp.
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BezierOptions {
+    pub tolerance: f32,     // pixels a point may be from its segment
+    pub angle: f32,         // degrees the path has to turn through at a corner
+    pub window: usize,      // points either side the turn and the end tangents are measured over
+    pub min_segment: usize, // fewest points between two corners
+    pub iterations: usize,  // newton steps on the parameters before splitting a segment
+}
+
+impl Default for BezierOptions {
+    fn default() -> Self {
+        BezierOptions {
+            tolerance: 1.0,
+            angle: 60.0,
+            window: 3,
+            min_segment: 4,
+            iterations: 4,
+        }
+    }
+}
+
+// start, two control points, end
+pub type Cubic = [(f32, f32); 4];
+
+// segments join end to start, each takes an equal share of t in [0, 1]
+#[derive(Debug, Clone, PartialEq)]
+pub struct BezierCurve {
+    pub segments: Vec<Cubic>,
+}
+
+fn point(c: &Cubic, u: f32) -> (f32, f32) {
+    let v = 1.0 - u;
+    let b = [v * v * v, 3.0 * u * v * v, 3.0 * u * u * v, u * u * u];
+    (
+        b.iter().zip(c).map(|(b, p)| b * p.0).sum(),
+        b.iter().zip(c).map(|(b, p)| b * p.1).sum(),
+    )
+}
+
+// first and second derivatives
+fn derivatives(c: &Cubic, u: f32) -> ((f32, f32), (f32, f32)) {
+    let v = 1.0 - u;
+    let d = |i: usize, j: usize| (c[j].0 - c[i].0, c[j].1 - c[i].1);
+    let (d0, d1, d2) = (d(0, 1), d(1, 2), d(2, 3));
+    let first = (
+        3.0 * (v * v * d0.0 + 2.0 * u * v * d1.0 + u * u * d2.0),
+        3.0 * (v * v * d0.1 + 2.0 * u * v * d1.1 + u * u * d2.1),
+    );
+    let second = (
+        6.0 * (v * (d1.0 - d0.0) + u * (d2.0 - d1.0)),
+        6.0 * (v * (d1.1 - d0.1) + u * (d2.1 - d1.1)),
+    );
+    (first, second)
+}
+
+impl BezierCurve {
+    pub fn evaluate(&self, t: f32) -> (f32, f32) {
+        let n = self.segments.len();
+        if n == 0 {
+            return (0.0, 0.0);
+        }
+        let s = t.clamp(0.0, 1.0) * n as f32;
+        let i = (s as usize).min(n - 1);
+        point(&self.segments[i], s - i as f32)
+    }
+
+    pub fn sample(&self, n: usize) -> Vec<(f32, f32)> {
+        let steps = n.max(2) - 1;
+        (0..=steps)
+            .map(|i| self.evaluate(i as f32 / steps as f32))
+            .collect()
+    }
+
+    // numbers it takes to write down, the start and then three points per segment
+    pub fn parameters(&self) -> usize {
+        if self.segments.is_empty() {
+            0
+        } else {
+            2 + self.segments.len() * 6
+        }
+    }
+
+    // SVG path data with native cubic commands
+    pub fn to_svg_path(&self) -> String {
+        let mut d = vec![];
+        let mut last = None;
+        for [p0, p1, p2, p3] in self.segments.iter() {
+            if last != Some(*p0) {
+                d.push(format!("M{:.2} {:.2}", p0.0, p0.1));
+            }
+            d.push(format!(
+                "C{:.2} {:.2} {:.2} {:.2} {:.2} {:.2}",
+                p1.0, p1.1, p2.0, p2.1, p3.0, p3.1
+            ));
+            last = Some(*p3);
+        }
+        d.join(" ")
+    }
+
+    // one Desmos expression per segment, t in [0, 1] on each, y negated like
+    // FourierSeries::to_equation
+    pub fn to_equations(&self) -> Vec<String> {
+        let bernstein = |a: f32, b: f32, c: f32, d: f32| {
+            format!("(1-t)^3*{a}+3t(1-t)^2*{b}+3t^2(1-t)*{c}+t^3*{d}")
+        };
+        self.segments
+            .iter()
+            .map(|[p0, p1, p2, p3]| {
+                format!(
+                    "(({}),-({}))",
+                    bernstein(p0.0, p1.0, p2.0, p3.0),
+                    bernstein(p0.1, p1.1, p2.1, p3.1)
+                )
+            })
+            .collect()
+    }
+}
+
+fn normalise(v: (f32, f32)) -> Option<(f32, f32)> {
+    let len = v.0.hypot(v.1);
+    (len > 1e-6).then(|| (v.0 / len, v.1 / len))
+}
+
+fn direction(from: (f32, f32), to: (f32, f32)) -> Option<(f32, f32)> {
+    normalise((to.0 - from.0, to.1 - from.1))
+}
+
+// Schneider's least squares for the control point distances along fixed end tangents, falling
+// back to a third of the chord when that goes wrong
+fn generate(points: &[(f32, f32)], u: &[f32], t1: (f32, f32), t2: (f32, f32)) -> Cubic {
+    let (first, last) = (points[0], points[points.len() - 1]);
+    let (mut c00, mut c01, mut c11, mut x0, mut x1) = (0.0, 0.0, 0.0, 0.0, 0.0);
+    for (p, &u) in points.iter().zip(u) {
+        let v = 1.0 - u;
+        let (b0, b1, b2, b3) = (v * v * v, 3.0 * u * v * v, 3.0 * u * u * v, u * u * u);
+        let a1 = (t1.0 * b1, t1.1 * b1);
+        let a2 = (t2.0 * b2, t2.1 * b2);
+        c00 += a1.0 * a1.0 + a1.1 * a1.1;
+        c01 += a1.0 * a2.0 + a1.1 * a2.1;
+        c11 += a2.0 * a2.0 + a2.1 * a2.1;
+        let rest = (
+            p.0 - (first.0 * (b0 + b1) + last.0 * (b2 + b3)),
+            p.1 - (first.1 * (b0 + b1) + last.1 * (b2 + b3)),
+        );
+        x0 += a1.0 * rest.0 + a1.1 * rest.1;
+        x1 += a2.0 * rest.0 + a2.1 * rest.1;
+    }
+    let chord = (last.0 - first.0).hypot(last.1 - first.1);
+    let det = c00 * c11 - c01 * c01;
+    let (mut alpha1, mut alpha2) = if det.abs() > 1e-12 {
+        ((x0 * c11 - x1 * c01) / det, (c00 * x1 - c01 * x0) / det)
+    } else {
+        (0.0, 0.0)
+    };
+    let epsilon = 1e-6 * chord;
+    if alpha1 < epsilon || alpha2 < epsilon {
+        (alpha1, alpha2) = (chord / 3.0, chord / 3.0);
+    }
+    [
+        first,
+        (first.0 + t1.0 * alpha1, first.1 + t1.1 * alpha1),
+        (last.0 + t2.0 * alpha2, last.1 + t2.1 * alpha2),
+        last,
+    ]
+}
+
+// furthest point from its place on the cubic, and its index
+fn max_error(cubic: &Cubic, points: &[(f32, f32)], u: &[f32]) -> (f32, usize) {
+    let mut worst = (0.0, points.len() / 2);
+    for (i, (p, &u)) in points.iter().zip(u).enumerate() {
+        let q = point(cubic, u);
+        let error = (p.0 - q.0).hypot(p.1 - q.1);
+        if error > worst.0 {
+            worst = (error, i);
+        }
+    }
+    worst
+}
+
+// one newton step towards the parameter of each point's closest point on the cubic
+fn reparameterise(cubic: &Cubic, points: &[(f32, f32)], u: &mut [f32]) {
+    for (p, u) in points.iter().zip(u.iter_mut()) {
+        let q = point(cubic, *u);
+        let (d1, d2) = derivatives(cubic, *u);
+        let (dx, dy) = (q.0 - p.0, q.1 - p.1);
+        let numerator = dx * d1.0 + dy * d1.1;
+        let denominator = d1.0 * d1.0 + d1.1 * d1.1 + dx * d2.0 + dy * d2.1;
+        if denominator.abs() > 1e-12 {
+            *u = (*u - numerator / denominator).clamp(0.0, 1.0);
+        }
+    }
+}
+
+// direction the curve leaves points[i] in towards points[j], averaged over a few points since
+// single pixel steps only go in eight directions
+fn tangent(points: &[(f32, f32)], i: usize, j: usize) -> (f32, f32) {
+    direction(points[i], points[j])
+        .or_else(|| direction(points[0], points[points.len() - 1]))
+        .unwrap_or((1.0, 0.0))
+}
+
+fn fit_cubic(
+    points: &[(f32, f32)],
+    t1: (f32, f32),
+    t2: (f32, f32),
+    options: &BezierOptions,
+    out: &mut Vec<Cubic>,
+) {
+    let n = points.len();
+    let (first, last) = (points[0], points[n - 1]);
+    if n <= 2 {
+        let third = (last.0 - first.0).hypot(last.1 - first.1) / 3.0;
+        out.push([
+            first,
+            (first.0 + t1.0 * third, first.1 + t1.1 * third),
+            (last.0 + t2.0 * third, last.1 + t2.1 * third),
+            last,
+        ]);
+        return;
+    }
+
+    let mut u = piecewise::chord_lengths(points);
+    let mut cubic = generate(points, &u, t1, t2);
+    let (mut error, mut split) = max_error(&cubic, points, &u);
+    // close enough that better parameters may get it within the tolerance
+    if error > options.tolerance && error < 4.0 * options.tolerance {
+        for _ in 0..options.iterations {
+            reparameterise(&cubic, points, &mut u);
+            cubic = generate(points, &u, t1, t2);
+            (error, split) = max_error(&cubic, points, &u);
+            if error <= options.tolerance {
+                break;
+            }
+        }
+    }
+    if error <= options.tolerance {
+        out.push(cubic);
+        return;
+    }
+
+    // split at the worst point, both halves share its tangent
+    let split = split.clamp(1, n - 2);
+    let w = options.window.max(1);
+    let centre = direction(
+        points[(split + w).min(n - 1)],
+        points[split.saturating_sub(w)],
+    )
+    .unwrap_or_else(|| tangent(points, n - 1, 0));
+    fit_cubic(&points[..=split], t1, centre, options, out);
+    fit_cubic(&points[split..], (-centre.0, -centre.1), t2, options, out);
+}
+
+// cubics through the pieces of `points` between corners
+pub fn fit_points(
+    points: &[(f32, f32)],
+    corners: &[usize],
+    options: &BezierOptions,
+) -> BezierCurve {
+    if points.is_empty() {
+        return BezierCurve { segments: vec![] };
+    }
+    let mut cuts = vec![0];
+    cuts.extend(
+        corners
+            .iter()
+            .copied()
+            .filter(|&c| c > 0 && c + 1 < points.len()),
+    );
+    cuts.push(points.len().saturating_sub(1));
+
+    let mut segments = vec![];
+    let w = options.window.max(1);
+    for c in cuts.windows(2) {
+        let piece = &points[c[0]..=c[1]];
+        let n = piece.len();
+        if n < 2 {
+            continue;
+        }
+        let t1 = tangent(piece, 0, w.min(n - 1));
+        let t2 = tangent(piece, n - 1, (n - 1).saturating_sub(w));
+        fit_cubic(piece, t1, t2, options, &mut segments);
+    }
+    BezierCurve { segments }
+}
+
+pub fn fit(path: &Path, options: &BezierOptions) -> BezierCurve {
+    let mut path = path.clone();
+    path.dedup(); // repeated points have no direction
+    let corners = piecewise::find_corners(
+        &path,
+        None,
+        &PiecewiseOptions {
+            angle: options.angle,
+            window: options.window,
+            min_segment: options.min_segment,
+            ..Default::default()
+        },
+    );
+    // the tracer jumps back when it backtracks, each jump is a straight segment of its own
+    let mut cuts: Vec<usize> = path
+        .windows(2)
+        .enumerate()
+        .filter(|(_, w)| (w[0].0 - w[1].0).abs() > 1 || (w[0].1 - w[1].1).abs() > 1)
+        .flat_map(|(i, _)| [i, i + 1])
+        .chain(corners)
+        .collect();
+    cuts.sort();
+    cuts.dedup();
+    let points: Vec<(f32, f32)> = path.iter().map(|&(x, y)| (x as f32, y as f32)).collect();
+    fit_points(&points, &cuts, options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn circle(r: f32) -> Path {
+        let mut path: Path = (0..720)
+            .map(|i| {
+                let a = (i as f32 / 2.0).to_radians();
+                (
+                    (50.0 + r * a.cos()).round() as i32,
+                    (50.0 + r * a.sin()).round() as i32,
+                )
+            })
+            .collect();
+        path.dedup();
+        path
+    }
+
+    fn distance_to_circle(p: (f32, f32), r: f32) -> f32 {
+        ((p.0 - 50.0).hypot(p.1 - 50.0) - r).abs()
+    }
+
+    #[test]
+    fn test_circle_few_smooth_segments() {
+        let path = circle(40.0);
+        let options = BezierOptions::default();
+        let curve = fit(&path, &options);
+        assert!(
+            (2..=12).contains(&curve.segments.len()),
+            "{}",
+            curve.segments.len()
+        );
+        assert!(curve.parameters() < path.len() * 2 / 10);
+        for p in curve.sample(500) {
+            assert!(
+                distance_to_circle(p, 40.0) < options.tolerance + 0.6,
+                "{p:?}"
+            );
+        }
+        // tangents line up where segments meet
+        for pair in curve.segments.windows(2) {
+            let (a, b) = (pair[0], pair[1]);
+            assert_eq!(a[3], b[0]);
+            let incoming = direction(a[2], a[3]).unwrap();
+            let outgoing = direction(b[0], b[1]).unwrap();
+            assert!(incoming.0 * outgoing.0 + incoming.1 * outgoing.1 > 0.99);
+        }
+    }
+
+    #[test]
+    fn test_corners_and_lines() {
+        // an L, one corner and two straight sides
+        let mut path: Path = (0..40).map(|i| (10, 50 - i)).collect();
+        path.extend((0..40).map(|i| (10 + i, 10)));
+        let curve = fit(&path, &BezierOptions::default());
+        assert_eq!(curve.segments.len(), 2);
+        assert_eq!(curve.segments[0][3], (10.0, 10.0));
+        for p in curve.sample(200) {
+            assert!(
+                (p.0 - 10.0).abs() < 1e-3 || (p.1 - 10.0).abs() < 1e-3,
+                "{p:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_empty_and_single_point() {
+        let options = BezierOptions::default();
+        for path in [vec![], vec![(3, 4)], vec![(3, 4), (3, 4)]] {
+            let curve = fit(&path, &options);
+            assert!(curve.segments.is_empty());
+            assert_eq!(curve.parameters(), 0);
+            assert!(curve.to_equations().is_empty());
+        }
+        assert!(fit_points(&[], &[], &options).segments.is_empty());
+    }
+
+    #[test]
+    fn test_exports() {
+        let curve = BezierCurve {
+            segments: vec![
+                [(0.0, 0.0), (1.0, 2.0), (3.0, 2.0), (4.0, 0.0)],
+                [(4.0, 0.0), (5.0, -2.0), (7.0, -2.0), (8.0, 0.0)],
+            ],
+        };
+        assert_eq!(curve.evaluate(0.25), (2.0, 1.5));
+        assert_eq!(curve.evaluate(1.0), (8.0, 0.0));
+        assert_eq!(
+            curve.to_svg_path(),
+            "M0.00 0.00 C1.00 2.00 3.00 2.00 4.00 0.00 C5.00 -2.00 7.00 -2.00 8.00 0.00"
+        );
+        let equations = curve.to_equations();
+        assert_eq!(equations.len(), 2);
+        assert_eq!(
+            equations[0],
+            "(((1-t)^3*0+3t(1-t)^2*1+3t^2(1-t)*3+t^3*4),-((1-t)^3*0+3t(1-t)^2*2+3t^2(1-t)*2+t^3*0))"
+        );
+        assert_eq!(curve.parameters(), 14);
+    }
+}
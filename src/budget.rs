@@ -0,0 +1,291 @@
+use crate::fourier::FourierSeries;
+use crate::img_to_line::Path;
+use serde::{Deserialize, Serialize};
+
+// Shares a fixed amount of output between all the curves of a drawing instead of giving every
+// curve the same number of harmonics. By Parseval, dropping the harmonics above h costs the curve
+// the energy of those harmonics in mean squared error; weighted by the length of its path that is
+// the visual error to minimise, and each added harmonic is a step with a known cost and gain.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BudgetUnit {
+    Terms,      // coefficients of the series, counting k and -k separately
+    Characters, // of the Desmos equations, as equations.txt would have them
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Allocation {
+    Greedy, // the harmonic with the most error removed per unit of budget, one at a time
+    // the lagrangian optimum for the largest allocation within budget, with what is left over
+    // handed out greedily
+    RateDistortion,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BudgetOptions {
+    pub total: usize,
+    pub unit: BudgetUnit,
+    pub allocation: Allocation,
+}
+
+impl Default for BudgetOptions {
+    fn default() -> Self {
+        BudgetOptions {
+            total: 1000,
+            unit: BudgetUnit::Terms,
+            allocation: Allocation::Greedy,
+        }
+    }
+}
+
+// the cost and the error removed of going from h - 1 to h harmonics, steps[0] being h = 1 which
+// also pays for the constant term, so a curve with no harmonics is left out altogether. both are
+// measured on what would be written, the truncated series with its window, which tapers towards
+// the new highest harmonic and so changes every term as h goes up
+struct Ladder {
+    steps: Vec<(f32, f32)>,
+}
+
+impl Ladder {
+    fn new(path: &Path, series: &FourierSeries, unit: BudgetUnit) -> Ladder {
+        let highest = series.terms.iter().map(|t| t.k.abs() as usize).max();
+        let weight = path.len() as f32;
+        // cost of the first h harmonics and the error they remove
+        let totals = |h: usize| {
+            if h == 0 {
+                return (0.0, 0.0);
+            }
+            let truncated = series.truncated(h);
+            let cost = match unit {
+                BudgetUnit::Terms => truncated.terms.len(),
+                BudgetUnit::Characters => truncated.to_equation().len() + 1, // and its newline
+            };
+            // by Parseval, what is left is the energy of the dropped harmonics and of the part
+            // of the kept ones the window takes away
+            let removed: f32 = truncated
+                .terms
+                .iter()
+                .zip(truncated.windowed_terms())
+                .filter(|(t, _)| t.k != 0.0)
+                .map(|(t, w)| {
+                    let full = t.cx * t.cx + t.cy * t.cy;
+                    full - (t.cx - w.cx).powi(2) - (t.cy - w.cy).powi(2)
+                })
+                .sum();
+            (cost as f32, weight * removed)
+        };
+        let mut previous = (0.0, 0.0);
+        let steps = (1..=highest.unwrap_or(0))
+            .map(|h| {
+                let next = totals(h);
+                let step = (next.0 - previous.0, next.1 - previous.1);
+                previous = next;
+                step
+            })
+            .collect();
+        Ladder { steps }
+    }
+
+    // cost and error removed with the first h steps
+    fn totals(&self, h: usize) -> (f32, f32) {
+        self.steps[..h]
+            .iter()
+            .fold((0.0, 0.0), |(c, g), s| (c + s.0, g + s.1))
+    }
+}
+
+// repeatedly moves whichever curve removes the most error per unit of budget up to its next
+// number of harmonics, looking past steps that remove next to nothing on their own (the even
+// harmonics of a closed path are close to zero) and skipping moves that no longer fit
+fn fill(ladders: &[Ladder], harmonics: &mut [usize], budget: f32) {
+    let mut spent: f32 = ladders
+        .iter()
+        .zip(harmonics.iter())
+        .map(|(l, &h)| l.totals(h).0)
+        .sum();
+    loop {
+        let mut next: Option<(usize, usize, f32, f32)> = None; // curve, harmonics, cost, ratio
+        for (i, (ladder, &h)) in ladders.iter().zip(harmonics.iter()).enumerate() {
+            let (mut cost, mut gain) = (0.0, 0.0);
+            for (j, step) in ladder.steps.iter().enumerate().skip(h) {
+                cost += step.0;
+                gain += step.1;
+                if spent + cost > budget {
+                    break;
+                }
+                let ratio = gain / cost.max(f32::EPSILON);
+                if next.is_none_or(|n| ratio > n.3) {
+                    next = Some((i, j + 1, cost, ratio));
+                }
+            }
+        }
+        let Some((i, h, cost, _)) = next else {
+            break;
+        };
+        harmonics[i] = h;
+        spent += cost;
+    }
+}
+
+// for a given lambda every curve minimises its remaining error plus lambda times its cost,
+// the larger lambda the less is spent
+fn lagrangian(ladders: &[Ladder], lambda: f32) -> (Vec<usize>, f32) {
+    let mut spent = 0.0;
+    let harmonics = ladders
+        .iter()
+        .map(|l| {
+            let best = (0..=l.steps.len())
+                .map(|h| {
+                    let (cost, gain) = l.totals(h);
+                    (h, cost, lambda * cost - gain)
+                })
+                .min_by(|a, b| a.2.total_cmp(&b.2).then(a.0.cmp(&b.0)))
+                .unwrap();
+            spent += best.1;
+            best.0
+        })
+        .collect();
+    (harmonics, spent)
+}
+
+fn rate_distortion(ladders: &[Ladder], budget: f32) -> Vec<usize> {
+    // above the steepest gain per cost nothing is worth spending on
+    let mut high = ladders
+        .iter()
+        .flat_map(|l| (1..=l.steps.len()).map(|h| l.totals(h)))
+        .map(|(cost, gain)| gain / cost.max(f32::EPSILON))
+        .fold(0.0, f32::max)
+        + 1.0;
+    let mut low = 0.0;
+    let mut best = vec![0; ladders.len()];
+    for _ in 0..64 {
+        let lambda = 0.5 * (low + high);
+        let (harmonics, spent) = lagrangian(ladders, lambda);
+        if spent <= budget {
+            best = harmonics;
+            high = lambda;
+        } else {
+            low = lambda;
+        }
+    }
+    let (harmonics, spent) = lagrangian(ladders, low);
+    if spent <= budget {
+        best = harmonics;
+    }
+    best
+}
+
+// how many harmonics each curve keeps, 0 for curves that are left out. `curves[i]` is the series
+// fitted to `paths[i]` with as many harmonics as any curve may get
+pub fn allocate(paths: &[Path], curves: &[FourierSeries], options: &BudgetOptions) -> Vec<usize> {
+    let ladders: Vec<Ladder> = paths
+        .iter()
+        .zip(curves.iter())
+        .map(|(p, c)| Ladder::new(p, c, options.unit))
+        .collect();
+    let budget = options.total as f32;
+    let mut harmonics = match options.allocation {
+        Allocation::Greedy => vec![0; ladders.len()],
+        Allocation::RateDistortion => rate_distortion(&ladders, budget),
+    };
+    fill(&ladders, &mut harmonics, budget);
+    harmonics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fourier::Window;
+
+    fn circle(r: f32, wobble: f32) -> Path {
+        (0..(r as usize * 6))
+            .map(|i| {
+                let a = i as f32 / (r * 6.0) * std::f32::consts::TAU;
+                let r = r + wobble * (7.0 * a).sin();
+                ((100.0 + r * a.cos()) as i32, (100.0 + r * a.sin()) as i32)
+            })
+            .collect()
+    }
+
+    fn total_error(paths: &[Path], curves: &[FourierSeries], harmonics: &[usize]) -> f32 {
+        paths
+            .iter()
+            .zip(curves)
+            .zip(harmonics)
+            .map(|((p, c), &h)| {
+                let l = Ladder::new(p, c, BudgetUnit::Terms);
+                l.totals(l.steps.len()).1 - l.totals(h).1
+            })
+            .sum()
+    }
+
+    #[test]
+    fn test_allocation_within_budget() {
+        let paths = vec![circle(60.0, 6.0), circle(40.0, 0.0), circle(5.0, 0.0)];
+        let curves: Vec<FourierSeries> = paths.iter().map(|p| FourierSeries::fit(p)).collect();
+        for allocation in [Allocation::Greedy, Allocation::RateDistortion] {
+            let options = BudgetOptions {
+                total: 60,
+                allocation,
+                ..Default::default()
+            };
+            let harmonics = allocate(&paths, &curves, &options);
+            let terms: usize = curves
+                .iter()
+                .zip(harmonics.iter())
+                .map(|(c, &h)| c.truncated(h).terms.len())
+                .sum();
+            assert!(terms <= 60, "{allocation:?} {harmonics:?}");
+            // the wobbly circle needs its 7th harmonic
+            assert!(harmonics[0] >= 7, "{allocation:?} {harmonics:?}");
+            assert!(harmonics[0] > harmonics[2], "{allocation:?} {harmonics:?}");
+        }
+
+        // with room for everything nothing is cut
+        let options = BudgetOptions {
+            total: 100_000,
+            ..Default::default()
+        };
+        let harmonics = allocate(&paths, &curves, &options);
+        assert_eq!(total_error(&paths, &curves, &harmonics), 0.0);
+    }
+
+    #[test]
+    fn test_characters_and_dropped_curves() {
+        let paths = vec![circle(60.0, 6.0), circle(40.0, 3.0)];
+        // counted on the equations as written, the window tapering the terms that are kept
+        for window in [Window::None, Window::Hann, Window::Lanczos] {
+            let curves: Vec<FourierSeries> = paths
+                .iter()
+                .map(|p| FourierSeries::fit(p).with_window(window))
+                .collect();
+            for allocation in [Allocation::Greedy, Allocation::RateDistortion] {
+                let options = BudgetOptions {
+                    total: 1500,
+                    unit: BudgetUnit::Characters,
+                    allocation,
+                };
+                let harmonics = allocate(&paths, &curves, &options);
+                let characters: usize = curves
+                    .iter()
+                    .zip(harmonics.iter())
+                    .filter(|(_, &h)| h > 0)
+                    .map(|(c, &h)| c.truncated(h).to_equation().len() + 1)
+                    .sum();
+                let case = format!("{window:?} {allocation:?} {characters}");
+                assert!(characters <= 1500 && characters > 1000, "{case}");
+            }
+        }
+
+        let curves: Vec<FourierSeries> = paths.iter().map(|p| FourierSeries::fit(p)).collect();
+        // too little for anything but the biggest curve's first harmonic
+        let options = BudgetOptions {
+            total: 3,
+            ..Default::default()
+        };
+        assert_eq!(allocate(&paths, &curves, &options), vec![1, 0]);
+    }
+}
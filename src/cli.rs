@@ -0,0 +1,929 @@
+use clap::builder::PossibleValuesParser;
+use clap::{ArgAction, Args, Parser, Subcommand, ValueEnum};
+use line_to_equation::budget::{self, BudgetOptions};
+use line_to_equation::descriptors::EllipticOptions;
+use line_to_equation::filter::{Criterion, Ranking, Saliency};
+use line_to_equation::model::{CurveModel, ModelKind, SelectOptions};
+use line_to_equation::morph::{self, Interpolation, MorphOptions};
+use line_to_equation::piecewise::CornerDetector;
+use line_to_equation::pipeline::{self, *};
+use line_to_equation::render::RenderOptions;
+use line_to_equation::shape_index::{self, ShapeIndex};
+use line_to_equation::spline::SplineKind;
+use line_to_equation::{batch, config, fourier, img_to_line, metrics, Error, Result};
+use std::fmt::Display;
+use std::path::PathBuf;
+
+#[derive(Parser)]
+#[command(
+    version,
+    about = "Converts lines from an image into parametric equations using Fourier transforms"
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+
+    /// Also print stage timings (-v) and per-curve details (-vv)
+    #[arg(short, long, action = ArgAction::Count, global = true)]
+    pub verbose: u8,
+
+    /// Only print errors
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Run every stage, writing the intermediate images and every selected output
+    Run(RenderArgs),
+    /// Detect edges and write edges.png
+    Edges(EdgeArgs),
+    /// Trace edges into paths and write lines.png and lines.svg
+    Trace(TraceArgs),
+    /// Fit a Fourier series to every path and write equations.txt
+    Fit(FitArgs),
+    /// Fit the paths and write the curves in the selected formats
+    Render(RenderArgs),
+    /// Run every image in a directory (or matching a quoted glob) into its own subfolder, with summary.csv/json
+    Batch(BatchArgs),
+    /// Trace the longest path of every image in a directory (or matching a quoted glob) into a shape index
+    Index(IndexArgs),
+    /// Look up the longest path of an image in a shape index
+    Match(MatchArgs),
+    /// Morph the curves of one image into another's, writing morph.gif and morph.txt (Desmos, with a slider s)
+    Morph(MorphArgs),
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Detector {
+    Sobel,
+    Canny,
+    /// Trace the input as is, for line art
+    None,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum TraceColour {
+    White,
+    Black,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Window {
+    None,
+    /// Lanczos sigma factors, the least smoothing
+    Lanczos,
+    /// Fejér averaging, the most smoothing and no overshoot
+    Fejer,
+    /// Raised cosine
+    Hann,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Spline {
+    /// Continuous curvature through every knot
+    Natural,
+    /// Centripetal Catmull-Rom, no cusps or loops where knots are close
+    CatmullRom,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Model {
+    Fourier,
+    Piecewise,
+    Bezier,
+    Spline,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum BudgetUnit {
+    /// Coefficients of the series
+    Terms,
+    /// Characters of the Desmos equations
+    Characters,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Allocation {
+    /// One harmonic at a time to the curve it helps most per unit
+    Greedy,
+    /// Lagrangian rate-distortion optimum, topped up greedily
+    RateDistortion,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum Format {
+    /// Desmos equations, equations.txt
+    Txt,
+    /// Traced paths and curves as layers, lines.svg
+    Svg,
+    /// Rasterized curves over the faded input, equations.png
+    Png,
+    /// Epicycles of the longest curve, epicycles.gif
+    Gif,
+    /// Epicycles of the longest curve as numbered pngs, epicycles_0000.png...
+    Frames,
+    /// Oscilloscope XY audio, lines.wav
+    Wav,
+    Gcode,
+    Hpgl,
+    /// R12 drawing in millimetres, lines.dxf, see --dxf-units
+    Dxf,
+    /// Fidelity metrics against the edge map, metrics.json
+    Metrics,
+    /// Paths split at corners with each piece fitted on its own, as Desmos equations with t domains, piecewise.txt
+    Piecewise,
+    /// Cubic Bézier segments as Desmos equations, bezier.txt, also drawn as their own svg layer
+    Bezier,
+    /// Spline segments as Desmos equations, spline.txt
+    Spline,
+    /// Spline segments as LaTeX cases, spline.tex
+    SplineTex,
+}
+
+#[derive(Args)]
+pub struct EdgeArgs {
+    /// Input image
+    pub input: PathBuf,
+
+    /// Pipeline configuration, .toml or .json such as the config.toml of an earlier run, the options below override it
+    #[arg(short, long)]
+    pub config: Option<PathBuf>,
+
+    /// Built-in settings to start from, the options below override it
+    #[arg(short, long, value_parser = PossibleValuesParser::new(config::PRESETS), conflicts_with = "config")]
+    pub preset: Option<String>,
+
+    /// Directory the outputs are written to [default: generated]
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+
+    /// [default: sobel]
+    #[arg(short, long, value_enum)]
+    pub detector: Option<Detector>,
+
+    /// Sobel magnitude threshold [default: 128]
+    #[arg(long)]
+    pub threshold: Option<u8>,
+
+    /// Canny low (weak edge) threshold [default: 50]
+    #[arg(long)]
+    pub low_threshold: Option<f32>,
+
+    /// Canny high (strong edge) threshold [default: 100]
+    #[arg(long)]
+    pub high_threshold: Option<f32>,
+
+    /// Gaussian blur applied before edge detection, 0 for none [default: 0]
+    #[arg(long)]
+    pub blur_sigma: Option<f32>,
+
+    /// Threads for edge detection and fitting, 0 for one per core [default: 0]
+    #[arg(long)]
+    pub threads: Option<usize>,
+}
+
+#[derive(Args)]
+pub struct TraceArgs {
+    #[command(flatten)]
+    pub edges: EdgeArgs,
+
+    /// Colour of the pixels that are traced [default: white]
+    #[arg(long, value_enum)]
+    pub trace_colour: Option<TraceColour>,
+
+    /// Paths with this many points or fewer are dropped [default: 16]
+    #[arg(long)]
+    pub min_length: Option<usize>,
+
+    /// Only keep the first n paths, the longest unless --saliency is given, 0 keeps all of them, ignored with --budget [default: 32]
+    #[arg(long)]
+    pub max_curves: Option<usize>,
+
+    /// Drop paths shorter than this many pixels of arc length
+    #[arg(long)]
+    pub min_arc_length: Option<f32>,
+
+    /// Drop paths longer than this many pixels of arc length
+    #[arg(long)]
+    pub max_arc_length: Option<f32>,
+
+    /// Drop paths whose bounding box covers fewer square pixels
+    #[arg(long)]
+    pub min_area: Option<f32>,
+
+    /// Drop paths with a weaker mean gradient magnitude under them, the sharpest edges are around 1000
+    #[arg(long)]
+    pub min_strength: Option<f32>,
+
+    /// Drop paths that come closer than this many pixels to the border of the image
+    #[arg(long)]
+    pub border_margin: Option<f32>,
+
+    /// Only keep closed paths, whose ends are at most this many pixels apart
+    #[arg(long)]
+    pub max_gap: Option<f32>,
+
+    /// Region of interest, an image that is white where paths should be kept
+    #[arg(long)]
+    pub mask: Option<PathBuf>,
+
+    /// Fraction of a path's points that have to be inside --mask [default: 0.5 with --mask]
+    #[arg(long)]
+    pub min_inside: Option<f32>,
+
+    /// Rank paths by a mix of length, size, edge strength, distance from the border and closedness instead of length alone
+    #[arg(long)]
+    pub saliency: bool,
+}
+
+#[derive(Args)]
+pub struct FitArgs {
+    #[command(flatten)]
+    pub trace: TraceArgs,
+
+    /// Harmonics on either side of the constant term [default: 40]
+    #[arg(long)]
+    pub max_harmonics: Option<usize>,
+
+    /// Also split paths at corners and fit each piece with a line, polynomial or short series, written to piecewise.txt
+    #[arg(long)]
+    pub piecewise: bool,
+
+    /// Degrees a path has to turn through at a corner, implies --piecewise [default: 45]
+    #[arg(long)]
+    pub corner_angle: Option<f32>,
+
+    /// Find corners with the Harris detector on the image instead of the path's angle, implies --piecewise
+    #[arg(long)]
+    pub harris: bool,
+
+    /// Taper the higher harmonics when drawing and exporting, trading sharpness for less ringing at corners [default: none]
+    #[arg(long, value_enum)]
+    pub window: Option<Window>,
+
+    /// Also fit each path with cubic Bézier segments, written to bezier.txt and, unless --models picks one model per path, as an svg layer
+    #[arg(long)]
+    pub bezier: bool,
+
+    /// Pixels a point may be from its Bézier segment, implies --bezier [default: 1]
+    #[arg(long)]
+    pub bezier_tolerance: Option<f32>,
+
+    /// Also fit each path with a spline through its simplified points, written to spline.txt
+    #[arg(long, value_enum)]
+    pub spline: Option<Spline>,
+
+    /// Pixels the simplified path the spline goes through may be from the traced one [default: 1.5]
+    #[arg(long)]
+    pub spline_epsilon: Option<f32>,
+
+    /// Write each path with whichever of these models has the lowest error plus --lambda per parameter, comma separated
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub models: Vec<Model>,
+
+    /// Pixels of rms error a parameter has to save to be worth writing down, implies all --models if none are given [default: 0.01]
+    #[arg(long)]
+    pub lambda: Option<f32>,
+
+    /// Terms or characters for the whole drawing, shared between every path to keep the total error lowest, --max-harmonics each at most
+    #[arg(long)]
+    pub budget: Option<usize>,
+
+    /// What --budget counts, implies --budget [default: terms]
+    #[arg(long, value_enum)]
+    pub budget_unit: Option<BudgetUnit>,
+
+    /// How --budget is shared between curves, implies --budget [default: greedy]
+    #[arg(long, value_enum)]
+    pub allocation: Option<Allocation>,
+}
+
+#[derive(Args)]
+pub struct RenderArgs {
+    #[command(flatten)]
+    pub fit: FitArgs,
+
+    /// Comma separated output formats, defaults to the config's, otherwise all of them for run and png for render
+    #[arg(short, long, value_enum, value_delimiter = ',')]
+    pub format: Vec<Format>,
+
+    /// Distance in pixels within which a curve pixel matches an edge pixel, for metrics [default: 2]
+    #[arg(long)]
+    pub tolerance: Option<f32>,
+
+    /// Mark lines.dxf as millimetres with $INSUNITS and $MEASUREMENT. Its numbers are millimetres either way, pixels times export.dxf.mm_per_px (0.1), but R12 has no units header, so strict R12 readers refuse the file with it
+    #[arg(long)]
+    pub dxf_units: bool,
+}
+
+#[derive(Args)]
+pub struct BatchArgs {
+    #[command(flatten)]
+    pub render: RenderArgs,
+
+    /// Images processed at once, 0 for one per core
+    #[arg(short, long, default_value_t = 0)]
+    pub jobs: usize,
+}
+
+#[derive(Args)]
+pub struct IndexArgs {
+    #[command(flatten)]
+    pub trace: TraceArgs,
+
+    /// Harmonics in each shape's elliptic Fourier descriptors
+    #[arg(long, default_value_t = 10)]
+    pub harmonics: usize,
+}
+
+#[derive(Args)]
+pub struct MatchArgs {
+    #[command(flatten)]
+    pub trace: TraceArgs,
+
+    /// Shape index written by the index command
+    #[arg(short, long)]
+    pub index: PathBuf,
+
+    /// Number of matches to list
+    #[arg(short, default_value_t = 5)]
+    pub k: usize,
+}
+
+#[derive(Args)]
+pub struct MorphArgs {
+    #[command(flatten)]
+    pub fit: FitArgs,
+
+    /// Image whose curves the input's turn into
+    pub target: PathBuf,
+
+    /// Frames from one image to the other, the animation then morphs back
+    #[arg(long, default_value_t = 60)]
+    pub frames: usize,
+
+    /// Interpolate each harmonic's radius and phase instead of its coefficients
+    #[arg(long)]
+    pub spherical: bool,
+
+    /// Keep the target's start point and orientation as traced
+    #[arg(long)]
+    pub no_align: bool,
+}
+
+impl From<Format> for pipeline::Format {
+    fn from(format: Format) -> Self {
+        match format {
+            Format::Txt => pipeline::Format::Txt,
+            Format::Svg => pipeline::Format::Svg,
+            Format::Png => pipeline::Format::Png,
+            Format::Gif => pipeline::Format::Gif,
+            Format::Frames => pipeline::Format::Frames,
+            Format::Wav => pipeline::Format::Wav,
+            Format::Gcode => pipeline::Format::Gcode,
+            Format::Hpgl => pipeline::Format::Hpgl,
+            Format::Dxf => pipeline::Format::Dxf,
+            Format::Metrics => pipeline::Format::Metrics,
+            Format::Piecewise => pipeline::Format::Piecewise,
+            Format::Bezier => pipeline::Format::Bezier,
+            Format::Spline => pipeline::Format::Spline,
+            Format::SplineTex => pipeline::Format::SplineTex,
+        }
+    }
+}
+
+impl From<Window> for fourier::Window {
+    fn from(window: Window) -> Self {
+        match window {
+            Window::None => fourier::Window::None,
+            Window::Lanczos => fourier::Window::Lanczos,
+            Window::Fejer => fourier::Window::Fejer,
+            Window::Hann => fourier::Window::Hann,
+        }
+    }
+}
+
+impl From<BudgetUnit> for budget::BudgetUnit {
+    fn from(unit: BudgetUnit) -> Self {
+        match unit {
+            BudgetUnit::Terms => budget::BudgetUnit::Terms,
+            BudgetUnit::Characters => budget::BudgetUnit::Characters,
+        }
+    }
+}
+
+impl From<Allocation> for budget::Allocation {
+    fn from(allocation: Allocation) -> Self {
+        match allocation {
+            Allocation::Greedy => budget::Allocation::Greedy,
+            Allocation::RateDistortion => budget::Allocation::RateDistortion,
+        }
+    }
+}
+
+impl From<Model> for ModelKind {
+    fn from(model: Model) -> Self {
+        match model {
+            Model::Fourier => ModelKind::Fourier,
+            Model::Piecewise => ModelKind::Piecewise,
+            Model::Bezier => ModelKind::Bezier,
+            Model::Spline => ModelKind::Spline,
+        }
+    }
+}
+
+impl From<Spline> for SplineKind {
+    fn from(spline: Spline) -> Self {
+        match spline {
+            Spline::Natural => SplineKind::Natural,
+            Spline::CatmullRom => SplineKind::CatmullRom,
+        }
+    }
+}
+
+impl TraceColour {
+    fn rgba(self) -> image::Rgba<u8> {
+        match self {
+            TraceColour::White => img_to_line::WHITE,
+            TraceColour::Black => img_to_line::BLACK,
+        }
+    }
+}
+
+impl EdgeArgs {
+    // the config file or preset, with the options given on the command line on top
+    fn pipeline(&self) -> Result<Pipeline> {
+        let mut pipeline = match (&self.config, &self.preset) {
+            (Some(file), _) => config::load(file)?,
+            (None, Some(name)) => config::preset(name)?,
+            (None, None) => Pipeline::new(),
+        };
+
+        let current = pipeline.edges.detector;
+        pipeline.edges.detector = match (self.detector, current) {
+            (None, _)
+            | (Some(Detector::Sobel), EdgeDetector::Sobel { .. })
+            | (Some(Detector::Canny), EdgeDetector::Canny { .. }) => current,
+            (Some(Detector::Sobel), _) => EdgeOptions::default().detector,
+            (Some(Detector::Canny), _) => EdgeDetector::Canny {
+                low: 50.0,
+                high: 100.0,
+            },
+            (Some(Detector::None), _) => EdgeDetector::None,
+        };
+        match &mut pipeline.edges.detector {
+            EdgeDetector::Sobel { threshold } => {
+                *threshold = self.threshold.unwrap_or(*threshold);
+            }
+            EdgeDetector::Canny { low, high } => {
+                *low = self.low_threshold.unwrap_or(*low);
+                *high = self.high_threshold.unwrap_or(*high);
+            }
+            EdgeDetector::None => {}
+        }
+        if let Some(sigma) = self.blur_sigma {
+            pipeline.preprocess.blur_sigma = sigma;
+        }
+        if let Some(threads) = self.threads {
+            pipeline.threads = threads;
+        }
+        Ok(pipeline)
+    }
+
+    // the config's export options writing `formats`, and the effective config with them
+    fn export(&self, pipeline: &mut Pipeline, formats: Vec<pipeline::Format>) -> ExportOptions {
+        let mut export = pipeline.export.take().unwrap_or_default();
+        export.formats = formats;
+        // piecewise.txt and bezier.txt are written whenever those are fitted, and asking for them
+        // fits them
+        let piecewise = pipeline::Format::Piecewise;
+        if pipeline.fit.piecewise.is_some() && !export.formats.contains(&piecewise) {
+            export.formats.push(piecewise);
+        }
+        if export.formats.contains(&piecewise) && pipeline.fit.piecewise.is_none() {
+            pipeline.fit.piecewise = Some(Default::default());
+        }
+        let bezier = pipeline::Format::Bezier;
+        if pipeline.fit.bezier.is_some() && !export.formats.contains(&bezier) {
+            export.formats.push(bezier);
+        }
+        if export.formats.contains(&bezier) && pipeline.fit.bezier.is_none() {
+            pipeline.fit.bezier = Some(Default::default());
+        }
+        let spline = [pipeline::Format::Spline, pipeline::Format::SplineTex];
+        if pipeline.fit.spline.is_some() && !export.formats.contains(&spline[0]) {
+            export.formats.push(spline[0]);
+        }
+        if spline.iter().any(|f| export.formats.contains(f)) && pipeline.fit.spline.is_none() {
+            pipeline.fit.spline = Some(Default::default());
+        }
+        if !export.formats.contains(&pipeline::Format::Config) {
+            export.formats.push(pipeline::Format::Config);
+        }
+        if let Some(dir) = &self.output {
+            export.dir = dir.clone();
+        }
+        export
+    }
+}
+
+impl TraceArgs {
+    fn pipeline(&self) -> Result<Pipeline> {
+        let mut pipeline = self.edges.pipeline()?;
+        if let Some(colour) = self.trace_colour {
+            pipeline.trace.colour = colour.rgba();
+        }
+        if let Some(min_length) = self.min_length {
+            pipeline.trace.min_length = min_length;
+        }
+        let filter = &mut pipeline.filter;
+        if let Some(max_curves) = self.max_curves {
+            filter.max_curves = max_curves;
+        }
+        if self.min_arc_length.is_some() || self.max_arc_length.is_some() {
+            filter.criteria.push(Criterion::Length {
+                min: self.min_arc_length.unwrap_or(0.0),
+                max: self.max_arc_length,
+            });
+        }
+        if let Some(min) = self.min_area {
+            filter.criteria.push(Criterion::Area { min });
+        }
+        if let Some(min) = self.min_strength {
+            filter.criteria.push(Criterion::Strength { min });
+        }
+        if let Some(min) = self.border_margin {
+            filter.criteria.push(Criterion::Border { min });
+        }
+        if let Some(max_gap) = self.max_gap {
+            filter.criteria.push(Criterion::Closed { max_gap });
+        }
+        if let Some(mask) = &self.mask {
+            filter.mask = Some(mask.clone());
+        }
+        if self.min_inside.is_some() && filter.mask.is_none() {
+            // every point counts as inside without a mask, so the criterion would keep everything
+            return Err(Error::InvalidParameter(
+                "--min-inside needs a --mask".to_string(),
+            ));
+        }
+        if self.mask.is_some() || self.min_inside.is_some() {
+            let min = self.min_inside.unwrap_or(0.5);
+            filter.criteria.push(Criterion::Inside { min });
+        }
+        if self.saliency && !matches!(filter.ranking, Ranking::Saliency(_)) {
+            filter.ranking = Ranking::Saliency(Saliency::default());
+        }
+        Ok(pipeline)
+    }
+}
+
+impl FitArgs {
+    fn pipeline(&self) -> Result<Pipeline> {
+        let mut pipeline = self.trace.pipeline()?;
+        if let Some(max_harmonics) = self.max_harmonics {
+            pipeline.fit.max_harmonics = max_harmonics;
+        }
+        if let Some(window) = self.window {
+            pipeline.fit.window = window.into();
+        }
+        if self.piecewise || self.corner_angle.is_some() || self.harris {
+            let options = pipeline.fit.piecewise.get_or_insert_with(Default::default);
+            if let Some(angle) = self.corner_angle {
+                options.angle = angle;
+            }
+            if self.harris {
+                options.detector = CornerDetector::Harris {
+                    k: 0.04,
+                    threshold: 0.01,
+                };
+            }
+        }
+        if self.bezier || self.bezier_tolerance.is_some() {
+            let options = pipeline.fit.bezier.get_or_insert_with(Default::default);
+            if let Some(tolerance) = self.bezier_tolerance {
+                options.tolerance = tolerance;
+            }
+        }
+        if self.spline.is_some() || self.spline_epsilon.is_some() {
+            let options = pipeline.fit.spline.get_or_insert_with(Default::default);
+            if let Some(kind) = self.spline {
+                options.kind = kind.into();
+            }
+            if let Some(epsilon) = self.spline_epsilon {
+                options.epsilon = epsilon;
+            }
+        }
+        if !self.models.is_empty() || self.lambda.is_some() {
+            let select = pipeline
+                .fit
+                .select
+                .get_or_insert_with(SelectOptions::default);
+            if !self.models.is_empty() {
+                select.candidates = self.models.iter().map(|&m| m.into()).collect();
+            }
+            if let Some(lambda) = self.lambda {
+                select.lambda = lambda;
+            }
+        }
+        if self.budget.is_some() || self.budget_unit.is_some() || self.allocation.is_some() {
+            let options = pipeline
+                .fit
+                .budget
+                .get_or_insert_with(BudgetOptions::default);
+            if let Some(total) = self.budget {
+                options.total = total;
+            }
+            if let Some(unit) = self.budget_unit {
+                options.unit = unit.into();
+            }
+            if let Some(allocation) = self.allocation {
+                options.allocation = allocation.into();
+            }
+        }
+        Ok(pipeline)
+    }
+}
+
+impl RenderArgs {
+    // -f, or the config's formats, or `defaults`
+    fn export(&self, pipeline: &mut Pipeline, defaults: Vec<pipeline::Format>) -> ExportOptions {
+        let mut formats: Vec<pipeline::Format> = self.format.iter().map(|&f| f.into()).collect();
+        if formats.is_empty() {
+            formats = match &pipeline.export {
+                Some(export) => export.formats.clone(),
+                None => defaults,
+            };
+        }
+        let mut export = self.fit.trace.edges.export(pipeline, formats);
+        if let Some(tolerance) = self.tolerance {
+            export.tolerance = tolerance;
+        }
+        if self.dxf_units {
+            export.dxf.units = true;
+        }
+        export
+    }
+}
+
+// 0 quiet, 1 normal, 2 timings, 3 per-curve details
+struct Log {
+    level: u8,
+}
+
+impl Log {
+    fn info(&self, msg: impl Display) {
+        if self.level >= 1 {
+            println!("{msg}");
+        }
+    }
+
+    fn detail(&self, msg: impl Display) {
+        if self.level >= 3 {
+            println!("  {msg}");
+        }
+    }
+
+    fn report(&self, result: &PipelineResult) {
+        if self.level >= 2 {
+            for (stage, elapsed) in result.timings.iter() {
+                println!("{stage:?}: {elapsed:?}");
+            }
+        }
+        if !result.paths.is_empty() {
+            self.info(format!(
+                "traced {} paths, kept {}",
+                result.paths.len(),
+                result.selected.len()
+            ));
+        }
+        for (i, (line, curve)) in result.selected.iter().zip(result.curves.iter()).enumerate() {
+            let mut detail = format!(
+                "curve {i}: {} points, {} terms, rms {:.3}",
+                line.len(),
+                curve.terms.len(),
+                metrics::curve_rms(line, curve)
+            );
+            // with --select, when something other than the series did better
+            let chosen = result
+                .models
+                .get(i)
+                .filter(|m| m.kind() != ModelKind::Fourier);
+            if let Some(model) = chosen {
+                detail += &format!(
+                    ", written as {:?} with {} parameters, error {:.3} vs {:.3}",
+                    model.kind(),
+                    model.parameters(),
+                    model.error(line),
+                    curve.error(line)
+                );
+            }
+            // parameter counts of the other fits, to compare with the series' 2 per term
+            if let Some(spline) = result.splines.get(i) {
+                detail += &format!(
+                    ", spline {} segments / {} parameters vs {}",
+                    spline.segments.len(),
+                    spline.parameters(),
+                    curve.parameters()
+                );
+            }
+            self.detail(detail);
+        }
+        if let Some(m) = &result.metrics {
+            self.info(format!(
+                "Hausdorff {:.2}, Chamfer {:.2}, precision {:.3}, recall {:.3}, F1 {:.3}",
+                m.hausdorff, m.chamfer, m.precision, m.recall, m.f1
+            ));
+        }
+        for file in result.written.iter() {
+            self.info(format!("wrote {}", file.display()));
+        }
+    }
+}
+
+// runs the pipeline up to `last`, then writes the given formats from what it produced
+fn run_stages(
+    pipeline: Pipeline,
+    args: &EdgeArgs,
+    last: Stage,
+    export: ExportOptions,
+    log: &Log,
+) -> Result<()> {
+    let img = img_to_line::get_image(&args.input.to_string_lossy())?;
+    let mut result = pipeline.run_until(img, last)?;
+    if last < Stage::Export {
+        pipeline.write(&mut result, &export)?;
+    }
+    log.report(&result);
+    Ok(())
+}
+
+pub fn run(cli: Cli) -> Result<()> {
+    let log = Log {
+        level: if cli.quiet { 0 } else { 1 + cli.verbose },
+    };
+
+    let is_run = matches!(cli.command, Command::Run(_));
+    match cli.command {
+        Command::Edges(args) => {
+            let mut pipeline = args.pipeline()?;
+            let export = args.export(&mut pipeline, vec![pipeline::Format::Edges]);
+            run_stages(pipeline, &args, Stage::Edges, export, &log)
+        }
+        Command::Trace(args) => {
+            let mut pipeline = args.pipeline()?;
+            let formats = vec![pipeline::Format::Lines, pipeline::Format::Svg];
+            let mut export = args.edges.export(&mut pipeline, formats);
+            export.svg.curves = false;
+            run_stages(pipeline, &args.edges, Stage::Filter, export, &log)
+        }
+        Command::Fit(args) => {
+            let mut pipeline = args.pipeline()?;
+            let formats = vec![pipeline::Format::Txt];
+            let export = args.trace.edges.export(&mut pipeline, formats);
+            run_stages(pipeline, &args.trace.edges, Stage::Fit, export, &log)
+        }
+        Command::Render(args) | Command::Run(args) => {
+            let mut pipeline = args.fit.pipeline()?;
+            let defaults = if is_run {
+                Format::value_variants()
+                    .iter()
+                    .filter(|f| **f != Format::Frames)
+                    .map(|&f| f.into())
+                    .collect()
+            } else {
+                vec![pipeline::Format::Png]
+            };
+            let mut export = args.export(&mut pipeline, defaults);
+            if is_run {
+                for format in [pipeline::Format::Lines, pipeline::Format::Edges] {
+                    if !export.formats.contains(&format) {
+                        export.formats.insert(0, format);
+                    }
+                }
+            }
+            let input = &args.fit.trace.edges.input;
+            let img = img_to_line::get_image(&input.to_string_lossy())?;
+            log.report(&pipeline.export(export).run(img)?);
+            Ok(())
+        }
+        Command::Batch(args) => {
+            let render = &args.render;
+            let mut pipeline = render.fit.pipeline()?;
+            let formats = vec![
+                pipeline::Format::Txt,
+                pipeline::Format::Svg,
+                pipeline::Format::Png,
+            ];
+            let export = render.export(&mut pipeline, formats);
+            let inputs = batch::find_images(&render.fit.trace.edges.input.to_string_lossy())?;
+            log.info(format!("processing {} images", inputs.len()));
+
+            let pipeline = pipeline.export(export.clone());
+            let options = batch::BatchOptions { jobs: args.jobs };
+            let reports = batch::run(&pipeline, &inputs, &options, |r| match &r.error {
+                None => log.info(format!(
+                    "{}: {} curves, {:.0} ms",
+                    r.input.display(),
+                    r.curves,
+                    r.total_ms
+                )),
+                Some(e) => eprintln!("{}: {e}", r.input.display()),
+            });
+
+            let mut written = batch::save_summary(&export.dir, &reports)?;
+            let file = export.dir.join("config.toml");
+            config::save(&file, &pipeline, &export)?;
+            written.push(file);
+            let failed = reports.iter().filter(|r| r.error.is_some()).count();
+            log.info(format!("{} of {} images failed", failed, reports.len()));
+            for file in written.iter() {
+                log.info(format!("wrote {}", file.display()));
+            }
+            Ok(())
+        }
+        Command::Index(args) => {
+            let pipeline = args.trace.pipeline()?;
+            let edges = &args.trace.edges;
+            let inputs = batch::find_images(&edges.input.to_string_lossy())?;
+            let options = EllipticOptions {
+                harmonics: args.harmonics,
+                ..Default::default()
+            };
+            let mut index = ShapeIndex::new(options);
+            for e in index.add_images(&pipeline, &inputs) {
+                eprintln!("skipped {e}");
+            }
+
+            let dir = edges.output.clone().unwrap_or(ExportOptions::default().dir);
+            std::fs::create_dir_all(&dir).map_err(Error::io(&dir))?;
+            let file = dir.join("shapes.json");
+            index.save(&file)?;
+            log.info(format!("indexed {} shapes", index.shapes.len()));
+            log.info(format!("wrote {}", file.display()));
+            Ok(())
+        }
+        Command::Morph(args) => {
+            let pipeline = args.fit.pipeline()?;
+            let edges = &args.fit.trace.edges;
+            let mut fitted = vec![];
+            for input in [&edges.input, &args.target] {
+                let img = img_to_line::get_image(&input.to_string_lossy())?;
+                fitted.push(pipeline.run_until(img, Stage::Fit)?);
+            }
+            let (from, to) = (&fitted[0], &fitted[1]);
+            let options = MorphOptions {
+                interpolation: if args.spherical {
+                    Interpolation::Spherical
+                } else {
+                    Interpolation::Linear
+                },
+                align: !args.no_align,
+                frames: args.frames,
+                ..Default::default()
+            };
+            // longest first on both sides, whatever order the ranking left them in
+            let by_length = |result: &PipelineResult| {
+                let mut pairs: Vec<_> = result.selected.iter().zip(&result.curves).collect();
+                pairs.sort_by_key(|(path, _)| std::cmp::Reverse(path.len()));
+                pairs
+                    .into_iter()
+                    .map(|(_, c)| c.clone())
+                    .collect::<Vec<_>>()
+            };
+            let morphs = morph::morph_curves(&by_length(from), &by_length(to), &options);
+            let dims = (
+                from.image.width().max(to.image.width()),
+                from.image.height().max(to.image.height()),
+            );
+
+            let dir = edges.output.clone().unwrap_or(ExportOptions::default().dir);
+            std::fs::create_dir_all(&dir).map_err(Error::io(&dir))?;
+            let (gif, txt) = (dir.join("morph.gif"), dir.join("morph.txt"));
+            morph::save_gif(&gif, &morphs, dims, &options, &RenderOptions::default())?;
+            morph::save_equations(&txt, &morphs)?;
+            log.info(format!("morphing {} curves", morphs.len()));
+            for file in [gif, txt] {
+                log.info(format!("wrote {}", file.display()));
+            }
+            Ok(())
+        }
+        Command::Match(args) => {
+            let pipeline = args.trace.pipeline()?;
+            let index = ShapeIndex::load(&args.index)?;
+            let path = shape_index::longest_path(&pipeline, &args.trace.edges.input)?;
+            for (i, m) in index.query(&path, args.k).iter().enumerate() {
+                let source = m.source.as_ref().map(|s| format!(" ({})", s.display()));
+                println!(
+                    "{}. {}{}: {:.4}",
+                    i + 1,
+                    m.name,
+                    source.unwrap_or_default(),
+                    m.distance
+                );
+            }
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,292 @@
+use crate::error::{Error, Result};
+use crate::img_to_line;
+use crate::pipeline::*;
+use serde::Serialize;
+use serde_json::Value;
+use std::path::Path;
+
+// built-in starting points, a config file picks one with `preset = "photo"` and overrides fields
+pub const PRESETS: [&str; 4] = ["line-art", "photo", "logo", "sketch"];
+
+pub fn preset(name: &str) -> Result<Pipeline> {
+    let pipeline = Pipeline::new();
+    let pipeline = match name {
+        // dark lines on a light background, traced as is
+        "line-art" => pipeline
+            .edges(EdgeOptions {
+                detector: EdgeDetector::None,
+            })
+            .trace(TraceOptions {
+                colour: img_to_line::BLACK,
+                ..Default::default()
+            })
+            .filter(FilterOptions {
+                max_curves: 0,
+                ..Default::default()
+            }),
+        // blur away texture and noise, then keep the strongest outlines
+        "photo" => pipeline
+            .preprocess(PreprocessOptions { blur_sigma: 1.4 })
+            .edges(EdgeOptions {
+                detector: EdgeDetector::Canny {
+                    low: 50.0,
+                    high: 100.0,
+                },
+            })
+            .trace(TraceOptions {
+                min_length: 32,
+                ..Default::default()
+            })
+            .filter(FilterOptions {
+                max_curves: 32,
+                ..Default::default()
+            }),
+        // crisp flat shapes, few curves but sharp corners need more harmonics
+        "logo" => pipeline
+            .edges(EdgeOptions {
+                detector: EdgeDetector::Canny {
+                    low: 30.0,
+                    high: 90.0,
+                },
+            })
+            .filter(FilterOptions {
+                max_curves: 16,
+                ..Default::default()
+            })
+            .fit(FitOptions {
+                max_harmonics: 80,
+                ..Default::default()
+            }),
+        // faint pencil strokes, lots of short curves
+        "sketch" => pipeline
+            .preprocess(PreprocessOptions { blur_sigma: 1.0 })
+            .edges(EdgeOptions {
+                detector: EdgeDetector::Canny {
+                    low: 20.0,
+                    high: 60.0,
+                },
+            })
+            .trace(TraceOptions {
+                min_length: 24,
+                ..Default::default()
+            })
+            .filter(FilterOptions {
+                max_curves: 64,
+                ..Default::default()
+            })
+            .fit(FitOptions {
+                max_harmonics: 30,
+                ..Default::default()
+            }),
+        _ => {
+            return Err(Error::InvalidParameter(format!(
+                "unknown preset {name}, expected one of {}",
+                PRESETS.join(", ")
+            )))
+        }
+    };
+    Ok(pipeline)
+}
+
+fn config_error(e: impl std::fmt::Display) -> Error {
+    Error::Config(e.to_string())
+}
+
+// objects are merged key by key and anything else is replaced. a single key object over a
+// different single key is an enum switching variant (sobel -> canny), so that is replaced too
+fn merge(base: &mut Value, over: Value) {
+    match (base, over) {
+        (Value::Object(base), Value::Object(over))
+            if !(base.len() == 1 && over.len() == 1 && base.keys().ne(over.keys())) =>
+        {
+            for (key, value) in over {
+                match base.get_mut(&key) {
+                    Some(b) => merge(b, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, over) => *base = over,
+    }
+}
+
+// the fields of `value` on top of its preset, or on top of the defaults
+fn from_value(mut value: Value) -> Result<Pipeline> {
+    let base = match value.as_object_mut().and_then(|o| o.remove("preset")) {
+        Some(Value::String(name)) => preset(&name)?,
+        Some(v) => return Err(Error::Config(format!("preset should be a name, not {v}"))),
+        None => Pipeline::new(),
+    };
+    let mut merged = serde_json::to_value(&base).map_err(config_error)?;
+    merge(&mut merged, value);
+    serde_json::from_value(merged).map_err(config_error)
+}
+
+pub fn from_toml(text: &str) -> Result<Pipeline> {
+    from_value(toml::from_str(text).map_err(config_error)?)
+}
+
+pub fn from_json(text: &str) -> Result<Pipeline> {
+    from_value(serde_json::from_str(text).map_err(config_error)?)
+}
+
+// .json files are read as json, anything else as toml
+pub fn load(file: impl AsRef<Path>) -> Result<Pipeline> {
+    let file = file.as_ref();
+    let text = std::fs::read_to_string(file).map_err(Error::io(file))?;
+    let pipeline = match file.extension() {
+        Some(ext) if ext == "json" => from_json(&text),
+        _ => from_toml(&text),
+    };
+    pipeline.map_err(|e| match e {
+        Error::Config(msg) => Error::Config(format!("{}: {msg}", file.display())),
+        e => e,
+    })
+}
+
+// what actually ran, export is passed separately as Pipeline::write can be given its own
+#[derive(Serialize)]
+struct Effective<'a> {
+    preprocess: &'a PreprocessOptions,
+    edges: &'a EdgeOptions,
+    trace: &'a TraceOptions,
+    filter: &'a FilterOptions,
+    fit: &'a FitOptions,
+    export: &'a ExportOptions,
+    threads: usize,
+}
+
+pub fn to_toml(pipeline: &Pipeline, export: &ExportOptions) -> Result<String> {
+    let effective = Effective {
+        preprocess: &pipeline.preprocess,
+        edges: &pipeline.edges,
+        trace: &pipeline.trace,
+        filter: &pipeline.filter,
+        fit: &pipeline.fit,
+        export,
+        threads: pipeline.threads,
+    };
+    toml::to_string(&effective).map_err(config_error)
+}
+
+pub fn save(file: impl AsRef<Path>, pipeline: &Pipeline, export: &ExportOptions) -> Result<()> {
+    std::fs::write(&file, to_toml(pipeline, export)?).map_err(Error::io(&file))
+}
+
+// colours are "#rrggbb" or "#rrggbbaa" in config files
+pub(crate) mod hex_colour {
+    use image::Rgba;
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(colour: &Rgba<u8>, s: S) -> Result<S::Ok, S::Error> {
+        let [r, g, b, a] = colour.0;
+        if a == 255 {
+            s.serialize_str(&format!("#{r:02x}{g:02x}{b:02x}"))
+        } else {
+            s.serialize_str(&format!("#{r:02x}{g:02x}{b:02x}{a:02x}"))
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Rgba<u8>, D::Error> {
+        let hex = String::deserialize(d)?;
+        parse(&hex).ok_or_else(|| de::Error::custom(format!("{hex} is not a #rrggbb colour")))
+    }
+
+    fn parse(hex: &str) -> Option<Rgba<u8>> {
+        let digits = hex.strip_prefix('#')?;
+        if !digits.is_ascii() || !(digits.len() == 6 || digits.len() == 8) {
+            return None;
+        }
+        let mut rgba = [255; 4];
+        for (i, c) in rgba.iter_mut().take(digits.len() / 2).enumerate() {
+            *c = u8::from_str_radix(&digits[2 * i..2 * i + 2], 16).ok()?;
+        }
+        Some(Rgba(rgba))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_presets() {
+        for name in PRESETS {
+            assert!(preset(name).is_ok());
+        }
+        assert!(matches!(
+            preset("watercolour"),
+            Err(Error::InvalidParameter(_))
+        ));
+
+        let line_art = preset("line-art").unwrap();
+        assert!(matches!(line_art.edges.detector, EdgeDetector::None));
+        assert_eq!(line_art.trace.colour, img_to_line::BLACK);
+    }
+
+    #[test]
+    fn test_override_preset() {
+        let pipeline = from_toml(
+            "preset = \"photo\"\n\
+             [preprocess]\nblur_sigma = 2\n\
+             [edges.detector.canny]\nhigh = 120\n",
+        )
+        .unwrap();
+        assert_eq!(pipeline.preprocess.blur_sigma, 2.0);
+        assert!(matches!(
+            pipeline.edges.detector,
+            EdgeDetector::Canny {
+                low: 50.0,
+                high: 120.0
+            }
+        ));
+        assert_eq!(pipeline.trace.min_length, 32); // untouched preset field
+        assert!(pipeline.export.is_none());
+
+        // switching detector replaces the preset's instead of merging into it
+        let pipeline = from_json(
+            r##"{"preset": "photo", "edges": {"detector": {"sobel": {"threshold": 90}}},
+                "export": {"formats": ["svg", "metrics"], "render": {"stroke": "#ff000080"}}}"##,
+        )
+        .unwrap();
+        assert!(matches!(
+            pipeline.edges.detector,
+            EdgeDetector::Sobel { threshold: 90 }
+        ));
+        let export = pipeline.export.unwrap();
+        assert_eq!(export.formats, vec![Format::Svg, Format::Metrics]);
+        assert_eq!(export.render.stroke, image::Rgba([255, 0, 0, 128]));
+        assert_eq!(export.tolerance, 2.0); // default filled in
+    }
+
+    #[test]
+    fn test_config_errors() {
+        assert!(matches!(
+            from_toml("[filter]\nmax_curve = 3\n"),
+            Err(Error::Config(_))
+        ));
+        assert!(matches!(
+            from_toml("[trace]\ncolour = \"white\"\n"),
+            Err(Error::Config(_))
+        ));
+        assert!(matches!(
+            from_toml("preset = \"watercolour\"\n"),
+            Err(Error::InvalidParameter(_))
+        ));
+    }
+
+    #[test]
+    fn test_toml_round_trip() {
+        let pipeline = preset("sketch").unwrap();
+        let export = ExportOptions::default();
+        let text = to_toml(&pipeline, &export).unwrap();
+        let loaded = from_toml(&text).unwrap();
+        assert_eq!(
+            to_toml(&loaded, loaded.export.as_ref().unwrap()).unwrap(),
+            text
+        );
+        assert!(text.contains("[edges.detector.canny]"));
+    }
+}
@@ -0,0 +1,315 @@
+use crate::fourier::FourierSeries;
+use crate::img_to_line::Path;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+
+// points sampled along a fitted curve before taking its descriptors
+const CURVE_SAMPLES: usize = 512;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EllipticOptions {
+    pub harmonics: usize,
+    pub translation: bool, // leave out the centre
+    pub scale: bool,       // semi-major axis of the first harmonic becomes 1
+    pub rotation: bool,    // first harmonic's major axis along x
+    pub start_point: bool, // start at the end of the first harmonic's major axis
+}
+
+impl Default for EllipticOptions {
+    fn default() -> Self {
+        EllipticOptions {
+            harmonics: 10,
+            translation: true,
+            scale: true,
+            rotation: true,
+            start_point: true,
+        }
+    }
+}
+
+// Kuhl-Giardina elliptic Fourier descriptors of a closed contour
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EllipticDescriptors {
+    pub centre: (f32, f32),
+    pub harmonics: Vec<[f32; 4]>, // a, b, c, d for n = 1, 2, ...
+    pub options: EllipticOptions,
+}
+
+// the end points are next to each other, so the path goes all the way round an outline
+pub fn is_closed(path: &[(i32, i32)]) -> bool {
+    match (path.first(), path.last()) {
+        (Some(a), Some(b)) if path.len() > 2 => (a.0 - b.0).abs() <= 1 && (a.1 - b.1).abs() <= 1,
+        _ => false,
+    }
+}
+
+// x(t) = a0 + sum a_n cos(2πnt/T) + b_n sin(2πnt/T), y likewise with c and d, where t is the
+// arc length. a contour that is not closed is closed with a straight line back to the start
+pub fn elliptic(contour: &[(f32, f32)], options: &EllipticOptions) -> EllipticDescriptors {
+    let n = contour.len();
+    // segments with their cumulative arc length, repeated points do not move along the contour
+    let mut segments = Vec::with_capacity(n);
+    let mut t = 0.0;
+    for i in 0..n {
+        let (p, q) = (contour[i], contour[(i + 1) % n]);
+        let (dx, dy) = (q.0 - p.0, q.1 - p.1);
+        let dt = (dx * dx + dy * dy).sqrt();
+        if dt > 0.0 {
+            segments.push((dx, dy, dt, t, t + dt));
+            t += dt;
+        }
+    }
+    let perimeter = t;
+
+    let mut harmonics = vec![[0.0; 4]; options.harmonics];
+    if perimeter > 0.0 {
+        for (k, h) in harmonics.iter_mut().enumerate() {
+            let w = 2.0 * PI * (k + 1) as f32 / perimeter;
+            for &(dx, dy, dt, t0, t1) in segments.iter() {
+                let (sin0, cos0) = (w * t0).sin_cos();
+                let (sin1, cos1) = (w * t1).sin_cos();
+                h[0] += dx / dt * (cos1 - cos0);
+                h[1] += dx / dt * (sin1 - sin0);
+                h[2] += dy / dt * (cos1 - cos0);
+                h[3] += dy / dt * (sin1 - sin0);
+            }
+            let scale = perimeter / (2.0 * ((k + 1) as f32 * PI).powi(2));
+            h.iter_mut().for_each(|v| *v *= scale);
+        }
+    }
+
+    let mut descriptors = EllipticDescriptors {
+        centre: centre(contour, &segments, perimeter),
+        harmonics,
+        options: options.clone(),
+    };
+    descriptors.normalise();
+    descriptors
+}
+
+// a0 and c0, the mean of the contour over its arc length
+fn centre(
+    contour: &[(f32, f32)],
+    segments: &[(f32, f32, f32, f32, f32)],
+    perimeter: f32,
+) -> (f32, f32) {
+    let Some(&start) = contour.first() else {
+        return (0.0, 0.0);
+    };
+    if perimeter == 0.0 {
+        return start;
+    }
+    let (mut a0, mut c0) = (0.0, 0.0);
+    let (mut x, mut y) = (0.0, 0.0); // offset of the segment's start from the first point
+    for &(dx, dy, dt, t0, t1) in segments.iter() {
+        a0 += dx / (2.0 * dt) * (t1 * t1 - t0 * t0) + (x - dx / dt * t0) * dt;
+        c0 += dy / (2.0 * dt) * (t1 * t1 - t0 * t0) + (y - dy / dt * t0) * dt;
+        x += dx;
+        y += dy;
+    }
+    (start.0 + a0 / perimeter, start.1 + c0 / perimeter)
+}
+
+fn rotate_phase(h: &mut [f32; 4], angle: f32) {
+    let (sin, cos) = angle.sin_cos();
+    let [a, b, c, d] = *h;
+    *h = [
+        a * cos + b * sin,
+        -a * sin + b * cos,
+        c * cos + d * sin,
+        -c * sin + d * cos,
+    ];
+}
+
+impl EllipticDescriptors {
+    fn normalise(&mut self) {
+        let Some(&[a, b, c, d]) = self.harmonics.first() else {
+            return;
+        };
+        // moving the start point by theta shifts harmonic n's phase by n * theta. when the first
+        // harmonic is a circle (circles, squares) there is no major axis, so rotation and start
+        // point stay wherever they happened to be
+        let theta = 0.5 * (2.0 * (a * b + c * d)).atan2(a * a - b * b + c * c - d * d);
+        let mut first = [a, b, c, d];
+        rotate_phase(&mut first, theta);
+        let psi = first[2].atan2(first[0]);
+        let semi_major = first[0].hypot(first[2]);
+
+        let options = &self.options;
+        for (k, h) in self.harmonics.iter_mut().enumerate() {
+            if options.start_point {
+                rotate_phase(h, (k + 1) as f32 * theta);
+            }
+            if options.rotation {
+                let (sin, cos) = psi.sin_cos();
+                let [a, b, c, d] = *h;
+                *h = [
+                    a * cos + c * sin,
+                    b * cos + d * sin,
+                    -a * sin + c * cos,
+                    -b * sin + d * cos,
+                ];
+            }
+            if options.scale && semi_major > 0.0 {
+                h.iter_mut().for_each(|v| *v /= semi_major);
+            }
+        }
+        if options.scale && semi_major > 0.0 {
+            self.centre = (self.centre.0 / semi_major, self.centre.1 / semi_major);
+        }
+    }
+
+    // the centre (unless normalised away) followed by a, b, c, d of every harmonic
+    pub fn to_vector(&self) -> Vec<f32> {
+        let mut vector = Vec::with_capacity(self.harmonics.len() * 4 + 2);
+        if !self.options.translation {
+            vector.extend([self.centre.0, self.centre.1]);
+        }
+        vector.extend(self.harmonics.iter().flatten());
+        vector
+    }
+
+    // euclidean distance between the feature vectors, both should be taken with the same options.
+    // missing harmonics count as 0
+    pub fn distance(&self, other: &EllipticDescriptors) -> f32 {
+        let (a, b) = (self.to_vector(), other.to_vector());
+        let mut b_flipped = b.clone();
+        let distance = |b: &[f32]| {
+            let (short, long) = if a.len() < b.len() {
+                (&a[..], b)
+            } else {
+                (b, &a[..])
+            };
+            let shared: f32 = short.iter().zip(long).map(|(x, y)| (x - y).powi(2)).sum();
+            let rest: f32 = long[short.len()..].iter().map(|v| v * v).sum();
+            (shared + rest).sqrt()
+        };
+        // the start point is only fixed up to the opposite end of the major axis, which with
+        // rotation normalised flips the sign of the even harmonics
+        let both = |o: &EllipticOptions| o.start_point && o.rotation;
+        if !(both(&self.options) && both(&other.options)) {
+            return distance(&b);
+        }
+        let offset = if other.options.translation { 0 } else { 2 };
+        for (k, h) in b_flipped[offset..].chunks_mut(4).enumerate() {
+            if k % 2 == 1 {
+                h.iter_mut().for_each(|v| *v = -*v);
+            }
+        }
+        distance(&b).min(distance(&b_flipped))
+    }
+}
+
+pub fn path_descriptors(path: &Path, options: &EllipticOptions) -> EllipticDescriptors {
+    let contour: Vec<(f32, f32)> = path.iter().map(|&(x, y)| (x as f32, y as f32)).collect();
+    elliptic(&contour, options)
+}
+
+impl FourierSeries {
+    // descriptors of the traced half of the curve, for comparing outlines whatever the number of
+    // harmonics they were fitted with
+    pub fn elliptic_descriptors(&self, options: &EllipticOptions) -> EllipticDescriptors {
+        elliptic(&self.sample(CURVE_SAMPLES), options)
+    }
+
+    pub fn feature_vector(&self, options: &EllipticOptions) -> Vec<f32> {
+        self.elliptic_descriptors(options).to_vector()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // an ellipse with a bump so that rotation and start point matter
+    fn outline(angle: f32, scale: f32, offset: (f32, f32), start: usize) -> Vec<(f32, f32)> {
+        let n = 200;
+        let points: Vec<(f32, f32)> = (0..n)
+            .map(|i| {
+                let t = 2.0 * PI * i as f32 / n as f32;
+                let r = 1.0 + 0.2 * (3.0 * t).cos();
+                let (x, y) = (40.0 * r * t.cos(), 20.0 * r * t.sin());
+                let (sin, cos) = angle.sin_cos();
+                (
+                    scale * (x * cos - y * sin) + offset.0,
+                    scale * (x * sin + y * cos) + offset.1,
+                )
+            })
+            .collect();
+        points[start..]
+            .iter()
+            .chain(&points[..start])
+            .copied()
+            .collect()
+    }
+
+    #[test]
+    fn test_circle() {
+        let circle: Vec<(f32, f32)> = (0..360)
+            .map(|i| {
+                let t = (i as f32).to_radians();
+                (50.0 + 10.0 * t.cos(), 30.0 + 10.0 * t.sin())
+            })
+            .collect();
+        let options = EllipticOptions {
+            translation: false,
+            scale: false,
+            rotation: false,
+            start_point: false,
+            ..Default::default()
+        };
+        let efd = elliptic(&circle, &options);
+        assert!((efd.centre.0 - 50.0).abs() < 1e-2 && (efd.centre.1 - 30.0).abs() < 1e-2);
+        let [a, b, c, d] = efd.harmonics[0];
+        assert!((a - 10.0).abs() < 1e-2 && b.abs() < 1e-2 && c.abs() < 1e-2);
+        assert!((d - 10.0).abs() < 1e-2);
+        assert!(efd.harmonics[1..].iter().flatten().all(|v| v.abs() < 1e-2));
+    }
+
+    #[test]
+    fn test_normalised_invariance() {
+        let options = EllipticOptions::default();
+        let reference = elliptic(&outline(0.0, 1.0, (0.0, 0.0), 0), &options);
+        assert!((reference.harmonics[0][0] - 1.0).abs() < 1e-4);
+        for (angle, scale, offset, start) in [
+            (0.7, 2.5, (100.0, 40.0), 37),
+            (2.9, 0.5, (-20.0, 5.0), 120),
+            (-1.2, 1.0, (0.0, 0.0), 100),
+        ] {
+            let moved = elliptic(&outline(angle, scale, offset, start), &options);
+            assert!(reference.distance(&moved) < 1e-3);
+        }
+
+        // without normalisation the same outline elsewhere is far away
+        let raw = EllipticOptions {
+            translation: false,
+            scale: false,
+            rotation: false,
+            start_point: false,
+            ..Default::default()
+        };
+        let a = elliptic(&outline(0.0, 1.0, (0.0, 0.0), 0), &raw);
+        let b = elliptic(&outline(0.7, 2.5, (100.0, 40.0), 37), &raw);
+        assert!(a.distance(&b) > 10.0);
+    }
+
+    #[test]
+    fn test_curve_feature_vector() {
+        let path: Path = outline(0.3, 1.0, (60.0, 60.0), 0)
+            .iter()
+            .map(|&(x, y)| (x.round() as i32, y.round() as i32))
+            .collect();
+        let options = EllipticOptions::default();
+        let curve = FourierSeries::fit(&path);
+        let vector = curve.feature_vector(&options);
+        assert_eq!(vector.len(), options.harmonics * 4);
+
+        let from_path = path_descriptors(&path, &options);
+        let from_curve = curve.elliptic_descriptors(&options);
+        assert!(from_path.distance(&from_curve) < 0.05);
+        let line: Path = (0..100).map(|i| (i, i / 3)).collect();
+        assert!(!is_closed(&line) && is_closed(&[(0, 0), (4, 2), (1, 1)]));
+        assert!(from_path.distance(&path_descriptors(&line, &options)) > 0.5);
+    }
+}
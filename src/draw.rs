@@ -0,0 +1,157 @@
+use image::{Rgba, RgbaImage};
+
+fn put(img: &mut RgbaImage, x: i32, y: i32, col: Rgba<u8>) {
+    if x >= 0 && y >= 0 && (x as u32) < img.width() && (y as u32) < img.height() {
+        img.put_pixel(x as u32, y as u32, col);
+    }
+}
+
+// mix col into the pixel by the given coverage in [0, 1]
+fn blend(img: &mut RgbaImage, x: i32, y: i32, col: Rgba<u8>, coverage: f32) {
+    if x < 0 || y < 0 || x as u32 >= img.width() || y as u32 >= img.height() {
+        return;
+    }
+    let a = coverage.clamp(0.0, 1.0) * col[3] as f32 / 255.0;
+    let p = img.get_pixel_mut(x as u32, y as u32);
+    for c in 0..3 {
+        p[c] = (p[c] as f32 * (1.0 - a) + col[c] as f32 * a).round() as u8;
+    }
+    p[3] = p[3].max((a * 255.0) as u8);
+}
+
+// Xiaolin Wu's anti-aliased line
+pub fn draw_line_aa(
+    img: &mut RgbaImage,
+    (mut x0, mut y0): (f32, f32),
+    (mut x1, mut y1): (f32, f32),
+    col: Rgba<u8>,
+) {
+    let steep = (y1 - y0).abs() > (x1 - x0).abs();
+    if steep {
+        (x0, y0, x1, y1) = (y0, x0, y1, x1);
+    }
+    if x0 > x1 {
+        (x0, y0, x1, y1) = (x1, y1, x0, y0);
+    }
+    let mut plot = |x: i32, y: i32, c: f32| {
+        if steep {
+            blend(img, y, x, col, c)
+        } else {
+            blend(img, x, y, col, c)
+        }
+    };
+
+    let dx = x1 - x0;
+    let gradient = if dx == 0.0 { 1.0 } else { (y1 - y0) / dx };
+
+    // end points
+    let xend = x0.round();
+    let yend = y0 + gradient * (xend - x0);
+    let xgap = 1.0 - (x0 + 0.5).fract();
+    let xpxl1 = xend as i32;
+    plot(xpxl1, yend.floor() as i32, (1.0 - yend.fract()) * xgap);
+    plot(xpxl1, yend.floor() as i32 + 1, yend.fract() * xgap);
+    let mut intery = yend + gradient;
+
+    let xend = x1.round();
+    let yend = y1 + gradient * (xend - x1);
+    let xgap = (x1 + 0.5).fract();
+    let xpxl2 = xend as i32;
+    plot(xpxl2, yend.floor() as i32, (1.0 - yend.fract()) * xgap);
+    plot(xpxl2, yend.floor() as i32 + 1, yend.fract() * xgap);
+
+    for x in (xpxl1 + 1)..xpxl2 {
+        plot(x, intery.floor() as i32, 1.0 - intery.fract());
+        plot(x, intery.floor() as i32 + 1, intery.fract());
+        intery += gradient;
+    }
+}
+
+// Bresenham, anything outside the image is clipped
+pub fn draw_line(img: &mut RgbaImage, (x0, y0): (f32, f32), (x1, y1): (f32, f32), col: Rgba<u8>) {
+    let (mut x, mut y) = (x0.round() as i32, y0.round() as i32);
+    let (x1, y1) = (x1.round() as i32, y1.round() as i32);
+    let (dx, dy) = ((x1 - x).abs(), -(y1 - y).abs());
+    let (sx, sy) = (if x < x1 { 1 } else { -1 }, if y < y1 { 1 } else { -1 });
+    let mut err = dx + dy;
+    loop {
+        put(img, x, y, col);
+        if x == x1 && y == y1 {
+            break;
+        }
+        let e2 = 2 * err;
+        if e2 >= dy {
+            err += dy;
+            x += sx;
+        }
+        if e2 <= dx {
+            err += dx;
+            y += sy;
+        }
+    }
+}
+
+// midpoint circle
+pub fn draw_circle(img: &mut RgbaImage, (cx, cy): (f32, f32), radius: f32, col: Rgba<u8>) {
+    let (cx, cy) = (cx.round() as i32, cy.round() as i32);
+    let (mut x, mut y) = (radius.round() as i32, 0);
+    let mut err = 1 - x;
+    while x >= y {
+        for (i, j) in [
+            (x, y),
+            (y, x),
+            (-y, x),
+            (-x, y),
+            (-x, -y),
+            (-y, -x),
+            (y, -x),
+            (x, -y),
+        ] {
+            put(img, cx + i, cy + j, col);
+        }
+        y += 1;
+        if err < 0 {
+            err += 2 * y + 1;
+        } else {
+            x -= 1;
+            err += 2 * (y - x) + 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_draw_line_endpoints() {
+        let mut img = RgbaImage::new(10, 10);
+        let white = Rgba([255, 255, 255, 255]);
+        draw_line(&mut img, (1.0, 1.0), (8.0, 4.0), white);
+        assert_eq!(*img.get_pixel(1, 1), white);
+        assert_eq!(*img.get_pixel(8, 4), white);
+        assert_eq!(img.pixels().filter(|p| **p == white).count(), 8);
+        draw_line(&mut img, (-5.0, -5.0), (20.0, 20.0), white); // clipped, no panic
+    }
+
+    #[test]
+    fn test_draw_line_aa() {
+        let mut img = RgbaImage::from_pixel(10, 10, Rgba([0, 0, 0, 255]));
+        let white = Rgba([255, 255, 255, 255]);
+        draw_line_aa(&mut img, (1.0, 2.0), (8.0, 2.0), white);
+        assert_eq!(*img.get_pixel(4, 2), white); // horizontal lines are fully covered
+        draw_line_aa(&mut img, (1.0, 5.0), (8.0, 6.5), white);
+        let partial = img.get_pixel(6, 6)[0];
+        assert!(partial > 0 && partial < 255); // diagonal lines are shaded
+    }
+
+    #[test]
+    fn test_draw_circle() {
+        let mut img = RgbaImage::new(21, 21);
+        let white = Rgba([255, 255, 255, 255]);
+        draw_circle(&mut img, (10.0, 10.0), 5.0, white);
+        assert_eq!(*img.get_pixel(15, 10), white);
+        assert_eq!(*img.get_pixel(10, 5), white);
+        assert_eq!(*img.get_pixel(10, 10), Rgba([0, 0, 0, 0]));
+    }
+}
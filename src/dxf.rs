@@ -0,0 +1,234 @@
+use crate::error::{Error, Result};
+use crate::img_to_line::Path;
+use crate::model::CurveModel;
+use crate::plotter::{self, Polyline};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write as _;
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LayerMode {
+    ByKind,       // one layer for traced paths and one for fitted curves
+    PerComponent, // every path/curve on its own layer, colours cycle through the AutoCAD palette
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DxfOptions {
+    pub mm_per_px: f32,
+    pub samples: usize, // points per fitted curve
+    pub layers: LayerMode,
+    // $INSUNITS and $MEASUREMENT in the header (--dxf-units), newer than R12 so strict R12 readers
+    // refuse them
+    pub units: bool,
+}
+
+impl Default for DxfOptions {
+    fn default() -> Self {
+        DxfOptions {
+            mm_per_px: 0.1,
+            samples: 256,
+            layers: LayerMode::ByKind,
+            units: false,
+        }
+    }
+}
+
+struct Layer {
+    name: String,
+    colour: u8, // AutoCAD colour index
+}
+
+// group codes and values alternate one per line
+fn pair(dxf: &mut String, code: i32, value: impl std::fmt::Display) {
+    let _ = write!(dxf, "{code}\n{value}\n");
+}
+
+fn closed(polyline: &Polyline) -> bool {
+    match (polyline.first(), polyline.last()) {
+        (Some(a), Some(b)) if polyline.len() > 2 => {
+            (a.0 - b.0).abs() <= 1.0 && (a.1 - b.1).abs() <= 1.0
+        }
+        _ => false,
+    }
+}
+
+// R12 has no LWPOLYLINE/SPLINE, so every curve is a POLYLINE followed by its VERTEX list,
+// which is what R12 readers (and every laser cutter package) understand
+fn polyline_entity(dxf: &mut String, polyline: &Polyline, layer: &str, height: f32, scale: f32) {
+    pair(dxf, 0, "POLYLINE");
+    pair(dxf, 8, layer);
+    pair(dxf, 66, 1); // vertices follow
+    pair(dxf, 10, 0.0);
+    pair(dxf, 20, 0.0);
+    pair(dxf, 30, 0.0);
+    pair(dxf, 70, if closed(polyline) { 1 } else { 0 });
+    for &(x, y) in polyline.iter() {
+        pair(dxf, 0, "VERTEX");
+        pair(dxf, 8, layer);
+        pair(dxf, 10, format!("{:.4}", x * scale));
+        pair(dxf, 20, format!("{:.4}", (height - y) * scale)); // dxf y axis points up
+        pair(dxf, 30, 0.0);
+    }
+    pair(dxf, 0, "SEQEND");
+    pair(dxf, 8, layer);
+}
+
+pub fn to_dxf(
+    (width, height): (u32, u32),
+    paths: &[Path],
+    curves: &[impl CurveModel],
+    options: &DxfOptions,
+) -> String {
+    let mut entities: Vec<(Polyline, usize)> = vec![]; // polyline and index of its layer
+    let mut layers = vec![];
+
+    let groups = [
+        ("PATHS", 8, plotter::paths_to_polylines(paths)),
+        (
+            "CURVES",
+            1,
+            plotter::curves_to_polylines(curves, options.samples),
+        ),
+    ];
+    for (kind, colour, polylines) in groups {
+        match options.layers {
+            LayerMode::ByKind => {
+                if !polylines.is_empty() {
+                    layers.push(Layer {
+                        name: kind.to_string(),
+                        colour,
+                    });
+                    entities.extend(polylines.into_iter().map(|p| (p, layers.len() - 1)));
+                }
+            }
+            LayerMode::PerComponent => {
+                for (i, p) in polylines.into_iter().enumerate() {
+                    layers.push(Layer {
+                        name: format!("{kind}_{i}"),
+                        colour: (layers.len() % 254) as u8 + 1,
+                    });
+                    entities.push((p, layers.len() - 1));
+                }
+            }
+        }
+    }
+
+    let scale = options.mm_per_px;
+    let mut dxf = String::new();
+
+    pair(&mut dxf, 0, "SECTION");
+    pair(&mut dxf, 2, "HEADER");
+    pair(&mut dxf, 9, "$ACADVER");
+    pair(&mut dxf, 1, "AC1009");
+    if options.units {
+        pair(&mut dxf, 9, "$INSUNITS");
+        pair(&mut dxf, 70, 4); // millimetres
+        pair(&mut dxf, 9, "$MEASUREMENT");
+        pair(&mut dxf, 70, 1); // metric
+    }
+    pair(&mut dxf, 9, "$EXTMIN");
+    pair(&mut dxf, 10, 0.0);
+    pair(&mut dxf, 20, 0.0);
+    pair(&mut dxf, 9, "$EXTMAX");
+    pair(&mut dxf, 10, format!("{:.4}", width as f32 * scale));
+    pair(&mut dxf, 20, format!("{:.4}", height as f32 * scale));
+    pair(&mut dxf, 0, "ENDSEC");
+
+    pair(&mut dxf, 0, "SECTION");
+    pair(&mut dxf, 2, "TABLES");
+    pair(&mut dxf, 0, "TABLE");
+    pair(&mut dxf, 2, "LTYPE");
+    pair(&mut dxf, 70, 1);
+    pair(&mut dxf, 0, "LTYPE");
+    pair(&mut dxf, 2, "CONTINUOUS");
+    pair(&mut dxf, 70, 0);
+    pair(&mut dxf, 3, "Solid line");
+    pair(&mut dxf, 72, 65);
+    pair(&mut dxf, 73, 0);
+    pair(&mut dxf, 40, 0.0);
+    pair(&mut dxf, 0, "ENDTAB");
+    pair(&mut dxf, 0, "TABLE");
+    pair(&mut dxf, 2, "LAYER");
+    pair(&mut dxf, 70, layers.len());
+    for layer in layers.iter() {
+        pair(&mut dxf, 0, "LAYER");
+        pair(&mut dxf, 2, &layer.name);
+        pair(&mut dxf, 70, 0);
+        pair(&mut dxf, 62, layer.colour);
+        pair(&mut dxf, 6, "CONTINUOUS");
+    }
+    pair(&mut dxf, 0, "ENDTAB");
+    pair(&mut dxf, 0, "ENDSEC");
+
+    pair(&mut dxf, 0, "SECTION");
+    pair(&mut dxf, 2, "ENTITIES");
+    for (polyline, layer) in entities.iter() {
+        polyline_entity(
+            &mut dxf,
+            polyline,
+            &layers[*layer].name,
+            height as f32,
+            scale,
+        );
+    }
+    pair(&mut dxf, 0, "ENDSEC");
+    pair(&mut dxf, 0, "EOF");
+    dxf
+}
+
+pub fn save_dxf(
+    file: impl AsRef<std::path::Path>,
+    dims: (u32, u32),
+    paths: &[Path],
+    curves: &[impl CurveModel],
+    options: &DxfOptions,
+) -> Result<()> {
+    std::fs::write(&file, to_dxf(dims, paths, curves, options)).map_err(Error::io(&file))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fourier::FourierSeries;
+
+    #[test]
+    fn test_dxf_entities() {
+        let path: Path = (0..20).map(|i| (i, 10)).collect();
+        let curve = FourierSeries::fit(&path);
+        let dxf = to_dxf((20, 10), &[path], &[curve], &DxfOptions::default());
+        assert!(dxf.starts_with("0\nSECTION\n2\nHEADER\n"));
+        assert!(dxf.ends_with("0\nEOF\n"));
+        assert_eq!(dxf.matches("\nPOLYLINE\n").count(), 2);
+        assert_eq!(dxf.matches("\nSEQEND\n").count(), 2);
+        assert_eq!(dxf.matches("\nVERTEX\n8\nPATHS\n").count(), 20);
+        assert_eq!(dxf.matches("\nVERTEX\n8\nCURVES\n").count(), 256);
+        // first traced point (0, 10) is at the bottom left once y is flipped
+        assert!(dxf.contains("VERTEX\n8\nPATHS\n10\n0.0000\n20\n0.0000\n"));
+        // nothing in the header that R12 does not know about
+        assert!(!dxf.contains("$INSUNITS") && !dxf.contains("$MEASUREMENT"));
+
+        let options = DxfOptions {
+            units: true,
+            ..Default::default()
+        };
+        let dxf = to_dxf((20, 10), &[], &[] as &[FourierSeries], &options);
+        assert!(dxf.contains("9\n$INSUNITS\n70\n4\n"));
+    }
+
+    #[test]
+    fn test_dxf_layer_per_component() {
+        let paths: Vec<Path> = (0..3)
+            .map(|j| (0..20).map(|i| (i, j * 5)).collect())
+            .collect();
+        let options = DxfOptions {
+            layers: LayerMode::PerComponent,
+            ..Default::default()
+        };
+        let dxf = to_dxf((20, 20), &paths, &[] as &[FourierSeries], &options);
+        for i in 0..3 {
+            assert!(dxf.contains(&format!("0\nLAYER\n2\nPATHS_{i}\n")));
+        }
+        assert!(!dxf.contains("CURVES"));
+    }
+}
@@ -1,10 +1,11 @@
-use image::{DynamicImage, GenericImage, GenericImageView, ImageBuffer, Luma};
+use crate::error::{Error, Result};
+use image::{DynamicImage, GenericImage, GenericImageView};
 use num::integer::Roots;
 
 type SobelPoint = (i32, i32);
 
-const SOBEL_X: [[i32; 3]; 3] = [[-1, 0, 1], [-2, 0, 2], [-1, 0, 1]];
-const SOBEL_Y: [[i32; 3]; 3] = [[-1, -2, -1], [0, 0, 0], [1, 2, 1]];
+pub(crate) const SOBEL_X: [[i32; 3]; 3] = [[-1, 0, 1], [-2, 0, 2], [-1, 0, 1]];
+pub(crate) const SOBEL_Y: [[i32; 3]; 3] = [[-1, -2, -1], [0, 0, 0], [1, 2, 1]];
 
 pub fn gx_gy(img: &DynamicImage, x: u32, y: u32) -> SobelPoint {
     let mut gx = 0;
@@ -16,8 +17,9 @@ pub fn gx_gy(img: &DynamicImage, x: u32, y: u32) -> SobelPoint {
             let new_y = ((y as i32 + j - 1).max(0) as u32).min(img.height() - 1);
 
             let pixel = img.get_pixel(new_x, new_y)[0] as i32;
-            gx += SOBEL_X[i as usize][j as usize] * pixel;
-            gy += SOBEL_Y[i as usize][j as usize] * pixel;
+            // kernels are indexed [row][col], i.e. [y][x]
+            gx += SOBEL_X[j as usize][i as usize] * pixel;
+            gy += SOBEL_Y[j as usize][i as usize] * pixel;
         }
     }
 
@@ -32,7 +34,6 @@ pub fn edge_magnitude((gx, gy): (i32, i32)) -> f64 {
     ((gx.pow(2) + gy.pow(2)) as f64).sqrt()
 }
 
-#[allow(dead_code)]
 pub fn sobel_threshold(img: &DynamicImage, threshold: u8, use_g: bool) -> DynamicImage {
     let mut new_img = img.clone();
 
@@ -54,12 +55,10 @@ pub fn sobel_threshold(img: &DynamicImage, threshold: u8, use_g: bool) -> Dynami
     new_img
 }
 
-#[allow(dead_code)]
 pub fn sobel(img: &DynamicImage) -> DynamicImage {
     sobel_threshold(img, 0, true)
 }
 
-#[allow(dead_code)]
 pub fn sobel_default(img: &DynamicImage) -> DynamicImage {
     sobel_threshold(img, 128, false)
 }
@@ -85,12 +84,23 @@ pub fn pixel_dir_offsets(angle: f64) -> ((i32, i32), (i32, i32)) {
     }
 }
 
-pub fn lower_bound_cutoff_suppression(img: &mut DynamicImage) {
-    let gradient = intensity_gradient(img);
+// edge_direction is in radians with y pointing down, pixel_dir_offsets wants degrees in
+// [-22.5, 337.5) with y pointing up
+pub(crate) fn compass_angle(direction: f64) -> f64 {
+    let angle = (-direction).to_degrees();
+    if angle < -22.5 {
+        angle + 360.0
+    } else {
+        angle
+    }
+}
 
+// non-maximum suppression, blacks out pixels that are weaker than either neighbour along the gradient
+pub fn lower_bound_cutoff_suppression(img: &mut DynamicImage, gradient: &[Vec<(f64, f64)>]) {
     for x in 0..img.width() as i32 {
         for y in 0..img.height() as i32 {
-            let (offset_x, offset_y) = pixel_dir_offsets(gradient[x as usize][y as usize].1);
+            let angle = compass_angle(gradient[x as usize][y as usize].1);
+            let (offset_x, offset_y) = pixel_dir_offsets(angle);
             let (x1, y1) = (x + offset_x.0, y + offset_x.1);
             let (x2, y2) = (x + offset_y.0, y + offset_y.1);
             
@@ -109,30 +119,95 @@ pub fn lower_bound_cutoff_suppression(img: &mut DynamicImage) {
             }
         }
     }
-    // new_img
 }
 
-pub fn double_threshold(img: &DynamicImage, (low, high): (i32, i32)) -> DynamicImage {
-    let mut new_img = img.clone();
+const STRONG: image::Rgba<u8> = image::Rgba([255, 255, 255, 255]);
+const WEAK: image::Rgba<u8> = image::Rgba([128, 128, 128, 255]);
 
+// strong edges become white, weak edges grey and everything else black
+pub fn double_threshold(img: &DynamicImage, (low, high): (f32, f32)) -> DynamicImage {
+    let mut new_img = img.clone();
+    for (x, y, p) in img.pixels() {
+        let g = p[0] as f32;
+        let col = if g >= high {
+            STRONG
+        } else if g >= low {
+            WEAK
+        } else {
+            image::Rgba([0, 0, 0, 255])
+        };
+        new_img.put_pixel(x, y, col);
+    }
+    new_img
+}
 
+// keep weak edges that are connected to a strong edge, drop the rest
+pub fn hysteresis(img: &mut DynamicImage) {
+    let (w, h) = img.dimensions();
+    let mut stack: Vec<(u32, u32)> = img
+        .pixels()
+        .filter(|(_, _, p)| *p == STRONG)
+        .map(|(x, y, _)| (x, y))
+        .collect();
+
+    while let Some((x, y)) = stack.pop() {
+        for (i, j) in [
+            (-1, -1),
+            (0, -1),
+            (1, -1),
+            (-1, 0),
+            (1, 0),
+            (-1, 1),
+            (0, 1),
+            (1, 1),
+        ] {
+            let (nx, ny) = (x as i32 + i, y as i32 + j);
+            if nx < 0 || ny < 0 || nx as u32 >= w || ny as u32 >= h {
+                continue;
+            }
+            if img.get_pixel(nx as u32, ny as u32) == WEAK {
+                img.put_pixel(nx as u32, ny as u32, STRONG);
+                stack.push((nx as u32, ny as u32));
+            }
+        }
+    }
 
-    new_img
+    for x in 0..w {
+        for y in 0..h {
+            if img.get_pixel(x, y) == WEAK {
+                img.put_pixel(x, y, image::Rgba([0, 0, 0, 255]));
+            }
+        }
+    }
 }
 
-pub fn canny(img: &DynamicImage, low_threshold: f32, high_threshold: f32) -> DynamicImage {
-    assert!(low_threshold < high_threshold);
-    let mut new_img = img.clone();
+pub(crate) fn check_thresholds(low_threshold: f32, high_threshold: f32) -> Result<()> {
+    // also rejects nan
+    if low_threshold.partial_cmp(&high_threshold) != Some(std::cmp::Ordering::Less) {
+        return Err(Error::InvalidParameter(format!(
+            "canny low threshold {low_threshold} must be below the high threshold {high_threshold}"
+        )));
+    }
+    Ok(())
+}
 
-    new_img = gaussian_blur_5x5(&new_img);
-    println!("Gaussian blur done");
+// thresholds are on the gradient magnitude, 0 to 255
+pub fn canny(
+    img: &DynamicImage,
+    low_threshold: f32,
+    high_threshold: f32,
+) -> Result<DynamicImage> {
+    check_thresholds(low_threshold, high_threshold)?;
 
-    let gradient = intensity_gradient(&new_img);
-    let sobel_img = sobel(&new_img);
+    let blurred = gaussian_blur_5x5(img);
+    let gradient = intensity_gradient(&blurred);
+    let mut new_img = sobel(&blurred);
 
-    lower_bound_cutoff_suppression(&mut new_img);
+    lower_bound_cutoff_suppression(&mut new_img, &gradient);
+    let mut new_img = double_threshold(&new_img, (low_threshold, high_threshold));
+    hysteresis(&mut new_img);
 
-    new_img
+    Ok(new_img)
 }
 
 enum GaussianFilter {
@@ -198,7 +273,7 @@ const GAUSSIAN_3X3: [f64; 9] = [
     1.0 / 16.0,
 ];
 
-const GAUSSIAN_5X5: [f64; 25] = [
+pub(crate) const GAUSSIAN_5X5: [f64; 25] = [
     1.0 / 273.0,
     4.0 / 273.0,
     7.0 / 273.0,
@@ -278,17 +353,117 @@ const GAUSSIAN_7X7: [f64; 49] = [
     0.0 / 1003.0,
 ];
 
-#[allow(dead_code)]
+// normalised 1d kernel of radius 3 sigma
+pub(crate) fn gaussian_kernel(sigma: f32) -> (i32, Vec<f32>) {
+    let radius = (3.0 * sigma).ceil() as i32;
+    let mut kernel: Vec<f32> = (-radius..=radius)
+        .map(|i| (-(i * i) as f32 / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let sum: f32 = kernel.iter().sum();
+    kernel.iter_mut().for_each(|k| *k /= sum);
+    (radius, kernel)
+}
+
+// separable blur with a kernel of radius 3 sigma, sigma <= 0 returns the image unchanged
+pub fn gaussian_blur_sigma(img: &DynamicImage, sigma: f32) -> DynamicImage {
+    if sigma <= 0.0 {
+        return img.clone();
+    }
+    let (radius, kernel) = gaussian_kernel(sigma);
+
+    let src = img.to_rgba8();
+    let (w, h) = src.dimensions();
+    let pass = |src: &image::RgbaImage, dx: i32, dy: i32| {
+        image::RgbaImage::from_fn(w, h, |x, y| {
+            let mut acc = [0.0; 3];
+            for (k, weight) in kernel.iter().enumerate() {
+                let o = k as i32 - radius;
+                let sx = (x as i32 + o * dx).clamp(0, w as i32 - 1) as u32;
+                let sy = (y as i32 + o * dy).clamp(0, h as i32 - 1) as u32;
+                let p = src.get_pixel(sx, sy);
+                for c in 0..3 {
+                    acc[c] += p[c] as f32 * weight;
+                }
+            }
+            image::Rgba([
+                acc[0].round() as u8,
+                acc[1].round() as u8,
+                acc[2].round() as u8,
+                255,
+            ])
+        })
+    };
+    DynamicImage::ImageRgba8(pass(&pass(&src, 1, 0), 0, 1))
+}
+
 pub fn gaussian_blur_3x3(img: &DynamicImage) -> DynamicImage {
     gaussian_blur(img, GaussianFilter::K3x3(GAUSSIAN_3X3))
 }
 
-#[allow(dead_code)]
 pub fn gaussian_blur_5x5(img: &DynamicImage) -> DynamicImage {
     gaussian_blur(img, GaussianFilter::K5x5(GAUSSIAN_5X5))
 }
 
-#[allow(dead_code)]
 pub fn gaussian_blur_7x7(img: &DynamicImage) -> DynamicImage {
     gaussian_blur(img, GaussianFilter::K7x7(GAUSSIAN_7X7))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // left half black, right half white
+    fn step() -> DynamicImage {
+        let mut img = DynamicImage::new_rgb8(20, 20);
+        for x in 10..20 {
+            for y in 0..20 {
+                img.put_pixel(x, y, image::Rgba([255, 255, 255, 255]));
+            }
+        }
+        img
+    }
+
+    #[test]
+    fn test_gradient_direction() {
+        let img = step();
+        let (gx, gy) = gx_gy(&img, 10, 5);
+        assert!(gx > 0 && gy == 0);
+        assert_eq!(compass_angle(edge_direction((gx, gy))), 0.0);
+    }
+
+    // the kernels are [row][col], so gx has to see a vertical edge and gy a horizontal one
+    #[test]
+    fn test_sobel_axes() {
+        let vertical = step();
+        let (gx, gy) = gx_gy(&vertical, 10, 10);
+        assert_eq!((gx, gy), (4 * 255, 0));
+
+        let horizontal = vertical.rotate90();
+        let (gx, gy) = gx_gy(&horizontal, 10, 10);
+        assert_eq!(gx, 0);
+        assert_eq!(gy.abs(), 4 * 255);
+    }
+
+    #[test]
+    fn test_canny_thin_edge() {
+        let edges = canny(&step(), 50.0, 100.0).unwrap();
+        for y in 3..17 {
+            let row: Vec<u32> = (0..20)
+                .filter(|&x| edges.get_pixel(x, y) == STRONG)
+                .collect();
+            assert!(!row.is_empty() && row.len() <= 2, "row {y}: {row:?}");
+            assert!(row.iter().all(|&x| (8..=11).contains(&x)));
+        }
+    }
+
+    #[test]
+    fn test_gaussian_blur_sigma() {
+        let img = step();
+        assert_eq!(gaussian_blur_sigma(&img, 0.0), img);
+        let blurred = gaussian_blur_sigma(&img, 1.5);
+        assert_eq!(blurred.get_pixel(0, 10)[0], 0);
+        assert_eq!(blurred.get_pixel(19, 10)[0], 255);
+        let mid = blurred.get_pixel(10, 10)[0];
+        assert!(mid > 0 && mid < 255);
+    }
+}
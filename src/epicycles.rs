@@ -0,0 +1,215 @@
+use crate::draw::{draw_circle, draw_line};
+use crate::error::{Error, Result};
+use crate::fourier::{FourierSeries, Term};
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, Frame, Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+use std::fs::File;
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnimationFormat {
+    Gif,
+    PngSequence, // <file>_0000.png, <file>_0001.png, ...
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EpicycleOptions {
+    pub frames: usize,
+    pub width: u32,
+    pub height: u32,
+    pub circles: usize, // number of circles drawn, 0 for all of them
+    pub frame_delay_ms: u32,
+    pub format: AnimationFormat,
+    #[serde(with = "crate::config::hex_colour")]
+    pub background: Rgba<u8>,
+    #[serde(with = "crate::config::hex_colour")]
+    pub circle_colour: Rgba<u8>,
+    #[serde(with = "crate::config::hex_colour")]
+    pub arm_colour: Rgba<u8>,
+    #[serde(with = "crate::config::hex_colour")]
+    pub trace_colour: Rgba<u8>,
+}
+
+impl Default for EpicycleOptions {
+    fn default() -> Self {
+        EpicycleOptions {
+            frames: 120,
+            width: 480,
+            height: 360,
+            circles: 0,
+            frame_delay_ms: 40,
+            format: AnimationFormat::Gif,
+            background: Rgba([0, 0, 0, 255]),
+            circle_colour: Rgba([70, 70, 90, 255]),
+            arm_colour: Rgba([200, 200, 200, 255]),
+            trace_colour: Rgba([255, 200, 0, 255]),
+        }
+    }
+}
+
+// terms ordered the way the circles are chained, constant term first then largest radius first
+fn sorted_terms(series: &FourierSeries, circles: usize) -> Vec<Term> {
+    let mut terms = series.windowed_terms();
+    terms.sort_by(|a, b| {
+        let (ma, mb) = (a.cx.hypot(a.cy), b.cx.hypot(b.cy));
+        (b.k == 0.0).cmp(&(a.k == 0.0)).then(mb.total_cmp(&ma))
+    });
+    if circles > 0 {
+        terms.truncate(circles + 1); // + 1 for the constant term, which is not a circle
+    }
+    terms
+}
+
+// centres of each circle in the chain, the last entry is the tip
+fn chain(terms: &[Term], t: f32) -> Vec<(f32, f32)> {
+    let mut pos = (0.0, 0.0);
+    let mut points = Vec::with_capacity(terms.len());
+    for term in terms.iter() {
+        let (sin, cos) = (term.k * PI * t).sin_cos();
+        pos.0 += term.cx * cos - term.cy * sin;
+        pos.1 += term.cx * sin + term.cy * cos;
+        points.push(pos);
+    }
+    points
+}
+
+pub fn render_frames(series: &FourierSeries, options: &EpicycleOptions) -> Vec<RgbaImage> {
+    let terms = sorted_terms(series, options.circles);
+    let frames = options.frames.max(1);
+    let substeps = 4; // extra trace points per frame so the curve stays smooth
+    let tips: Vec<(f32, f32)> = (0..=frames * substeps)
+        .map(|i| {
+            *chain(&terms, 2.0 * i as f32 / (frames * substeps) as f32)
+                .last()
+                .unwrap()
+        })
+        .collect();
+
+    // fit the traced curve to the frame with a margin, keeping the aspect ratio
+    let (min_x, max_x, min_y, max_y) = tips.iter().fold(
+        (f32::MAX, f32::MIN, f32::MAX, f32::MIN),
+        |(a, b, c, d), &(x, y)| (a.min(x), b.max(x), c.min(y), d.max(y)),
+    );
+    let scale = (0.8 * options.width as f32 / (max_x - min_x).max(1.0))
+        .min(0.8 * options.height as f32 / (max_y - min_y).max(1.0));
+    let to_frame = |(x, y): (f32, f32)| {
+        (
+            options.width as f32 / 2.0 + (x - (min_x + max_x) / 2.0) * scale,
+            options.height as f32 / 2.0 + (y - (min_y + max_y) / 2.0) * scale,
+        )
+    };
+
+    (0..frames)
+        .map(|f| {
+            let mut img = RgbaImage::from_pixel(options.width, options.height, options.background);
+            let t = 2.0 * f as f32 / frames as f32;
+
+            for w in tips[..=f * substeps].windows(2) {
+                draw_line(
+                    &mut img,
+                    to_frame(w[0]),
+                    to_frame(w[1]),
+                    options.trace_colour,
+                );
+            }
+
+            let centres = chain(&terms, t);
+            for (i, w) in centres.windows(2).enumerate() {
+                let radius = terms[i + 1].cx.hypot(terms[i + 1].cy) * scale;
+                draw_circle(&mut img, to_frame(w[0]), radius, options.circle_colour);
+                draw_line(&mut img, to_frame(w[0]), to_frame(w[1]), options.arm_colour);
+            }
+            img
+        })
+        .collect()
+}
+
+pub fn save_gif(
+    file: impl AsRef<std::path::Path>,
+    frames: Vec<RgbaImage>,
+    delay_ms: u32,
+) -> Result<()> {
+    let mut encoder = GifEncoder::new(File::create(&file).map_err(Error::io(&file))?);
+    encoder
+        .set_repeat(Repeat::Infinite)
+        .map_err(Error::image(&file))?;
+    encoder
+        .encode_frames(
+            frames
+                .into_iter()
+                .map(|f| Frame::from_parts(f, 0, 0, Delay::from_numer_denom_ms(delay_ms, 1))),
+        )
+        .map_err(Error::image(&file))
+}
+
+pub fn save_png_sequence(prefix: impl AsRef<std::path::Path>, frames: &[RgbaImage]) -> Result<()> {
+    for (i, frame) in frames.iter().enumerate() {
+        let file = format!("{}_{i:04}.png", prefix.as_ref().display());
+        frame.save(&file).map_err(Error::image(&file))?;
+    }
+    Ok(())
+}
+
+// file is the gif path, or the prefix of the numbered pngs
+pub fn save_animation(
+    file: impl AsRef<std::path::Path>,
+    series: &FourierSeries,
+    options: &EpicycleOptions,
+) -> Result<()> {
+    let frames = render_frames(series, options);
+    match options.format {
+        AnimationFormat::Gif => save_gif(file, frames, options.frame_delay_ms),
+        AnimationFormat::PngSequence => save_png_sequence(file, &frames),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn circle() -> FourierSeries {
+        let path: Vec<(i32, i32)> = (0..64)
+            .map(|i| {
+                let a = 2.0 * PI * i as f32 / 64.0;
+                (50 + (20.0 * a.cos()) as i32, 50 + (20.0 * a.sin()) as i32)
+            })
+            .collect();
+        FourierSeries::fit(&path)
+    }
+
+    #[test]
+    fn test_sorted_terms() {
+        let terms = sorted_terms(&circle(), 3);
+        assert_eq!(terms.len(), 4);
+        assert_eq!(terms[0].k, 0.0);
+        let mags: Vec<f32> = terms[1..].iter().map(|t| t.cx.hypot(t.cy)).collect();
+        assert!(mags.windows(2).all(|w| w[0] >= w[1]));
+    }
+
+    #[test]
+    fn test_chain_tip_matches_series() {
+        let series = circle();
+        let terms = sorted_terms(&series, 0);
+        let tip = *chain(&terms, 0.3).last().unwrap();
+        let expected = series.evaluate(0.3);
+        assert!((tip.0 - expected.0).abs() < 1e-2 && (tip.1 - expected.1).abs() < 1e-2);
+    }
+
+    #[test]
+    fn test_render_frames() {
+        let options = EpicycleOptions {
+            frames: 5,
+            width: 64,
+            height: 48,
+            ..Default::default()
+        };
+        let frames = render_frames(&circle(), &options);
+        assert_eq!(frames.len(), 5);
+        assert_eq!(frames[0].dimensions(), (64, 48));
+        let traced = |img: &RgbaImage| img.pixels().filter(|p| **p == options.trace_colour).count();
+        assert!(traced(&frames[4]) > traced(&frames[1]));
+    }
+}
@@ -0,0 +1,51 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum Error {
+    Io(PathBuf, std::io::Error),
+    Image(PathBuf, image::ImageError), // decoding or encoding an image
+    InvalidParameter(String),
+    Config(String), // a config file that does not parse or names unknown fields
+    EmptyInput(String),
+    Parse(PathBuf, String), // a data file, such as a shape index, that could not be read back
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    // for map_err, keeps the file the error happened on
+    pub fn io(path: impl AsRef<Path>) -> impl FnOnce(std::io::Error) -> Error {
+        move |e| Error::Io(path.as_ref().to_path_buf(), e)
+    }
+
+    pub fn image(path: impl AsRef<Path>) -> impl FnOnce(image::ImageError) -> Error {
+        move |e| match e {
+            image::ImageError::IoError(e) => Error::Io(path.as_ref().to_path_buf(), e),
+            e => Error::Image(path.as_ref().to_path_buf(), e),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(path, e) => write!(f, "{}: {e}", path.display()),
+            Error::Image(path, e) => write!(f, "{}: {e}", path.display()),
+            Error::InvalidParameter(msg) => write!(f, "invalid parameter: {msg}"),
+            Error::Config(msg) => write!(f, "invalid config: {msg}"),
+            Error::EmptyInput(msg) => write!(f, "empty input: {msg}"),
+            Error::Parse(path, msg) => write!(f, "{}: {msg}", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(_, e) => Some(e),
+            Error::Image(_, e) => Some(e),
+            _ => None,
+        }
+    }
+}
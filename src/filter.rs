@@ -0,0 +1,362 @@
+use crate::error::{Error, Result};
+use crate::img_to_line::Path;
+use crate::parallel::{self, Plane};
+use image::{DynamicImage, GrayImage};
+use serde::{Deserialize, Serialize};
+
+// Which of the traced paths are worth fitting. Every path is measured once, criteria then drop
+// the ones outside their limits and a ranking orders the rest before the longest are kept.
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Criterion {
+    // arc length in pixels, without the tracer's jumps back
+    Length {
+        min: f32,
+        #[serde(default)]
+        max: Option<f32>,
+    },
+    // of the bounding box, in square pixels
+    Area {
+        min: f32,
+    },
+    // mean gradient magnitude of the image under the path
+    Strength {
+        min: f32,
+    },
+    // pixels between the path and the closest edge of the image
+    Border {
+        min: f32,
+    },
+    // pixels between the two ends
+    Closed {
+        max_gap: f32,
+    },
+    // fraction of the path's points on the mask, all of them without one
+    Inside {
+        min: f32,
+    },
+}
+
+// how much each feature counts towards a path's saliency, each is scaled to [0, 1] first
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Saliency {
+    pub length: f32,   // relative to the longest path
+    pub area: f32,     // relative to the biggest bounding box
+    pub strength: f32, // relative to the strongest path
+    pub border: f32,   // 1 from a tenth of the image's smaller side in
+    pub closed: f32,   // 1 when the ends meet, 0 when they are as far apart as the path is long
+    pub inside: f32,
+}
+
+impl Default for Saliency {
+    fn default() -> Self {
+        Saliency {
+            length: 1.0,
+            area: 0.5,
+            strength: 1.0,
+            border: 0.25,
+            closed: 0.25,
+            inside: 0.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Ranking {
+    Longest, // by number of points
+    Saliency(Saliency),
+}
+
+// what the criteria and the ranking look at, for one path
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Features {
+    pub points: usize,
+    pub length: f32,
+    pub area: f32,
+    pub strength: f32,
+    pub border: f32,
+    pub gap: f32,
+    pub inside: f32,
+}
+
+// the image the paths were traced from, as far as the features need it
+pub struct Context {
+    width: u32,
+    height: u32,
+    magnitude: Option<Plane>, // only when something looks at the strength
+    mask: Option<GrayImage>,  // scaled to the image
+}
+
+impl Context {
+    pub fn new(
+        img: &DynamicImage,
+        strength: bool,
+        mask: Option<&std::path::Path>,
+        threads: usize,
+    ) -> Result<Context> {
+        let (width, height) = (img.width(), img.height());
+        let magnitude = strength.then(|| {
+            let (gx, gy) = parallel::gradient(&parallel::channel(img, 0), threads);
+            parallel::map_rows(width, height, threads, |y, out| {
+                let w = width as usize;
+                let row = y * w..(y + 1) * w;
+                let (gx, gy) = (&gx.as_raw()[row.clone()], &gy.as_raw()[row]);
+                for ((m, x), y) in out.iter_mut().zip(gx).zip(gy) {
+                    *m = x.hypot(*y);
+                }
+            })
+        });
+        let mask = match mask {
+            Some(file) => {
+                let mask = image::open(file).map_err(Error::image(file))?;
+                if mask.width() == 0 || mask.height() == 0 {
+                    return Err(Error::EmptyInput(format!(
+                        "mask {} has no pixels",
+                        file.display()
+                    )));
+                }
+                let mask = mask.resize_exact(width, height, image::imageops::FilterType::Nearest);
+                Some(mask.to_luma8())
+            }
+            None => None,
+        };
+        Ok(Context {
+            width,
+            height,
+            magnitude,
+            mask,
+        })
+    }
+}
+
+impl Features {
+    pub fn of(path: &[(i32, i32)], context: &Context) -> Features {
+        let length = path
+            .windows(2)
+            .map(|w| (w[1].0 - w[0].0, w[1].1 - w[0].1))
+            .filter(|d| d.0.abs() <= 1 && d.1.abs() <= 1)
+            .map(|(dx, dy)| (dx as f32).hypot(dy as f32))
+            .sum();
+        let (w, h) = (context.width as i32, context.height as i32);
+        let inside_image = |&&(x, y): &&(i32, i32)| x >= 0 && y >= 0 && x < w && y < h;
+        let (mut min, mut max) = ((i32::MAX, i32::MAX), (i32::MIN, i32::MIN));
+        let mut border = f32::INFINITY;
+        for &(x, y) in path {
+            min = (min.0.min(x), min.1.min(y));
+            max = (max.0.max(x), max.1.max(y));
+            border = border.min(x.min(y).min(w - 1 - x).min(h - 1 - y) as f32);
+        }
+        let mean = |value: &dyn Fn(u32, u32) -> f32| {
+            let on: Vec<f32> = path
+                .iter()
+                .filter(inside_image)
+                .map(|&(x, y)| value(x as u32, y as u32))
+                .collect();
+            on.iter().sum::<f32>() / on.len().max(1) as f32
+        };
+        let strength = match &context.magnitude {
+            Some(m) => mean(&|x, y| m.get_pixel(x, y)[0]),
+            None => 0.0,
+        };
+        let inside = match &context.mask {
+            Some(m) => mean(&|x, y| (m.get_pixel(x, y)[0] >= 128) as u8 as f32),
+            None => 1.0,
+        };
+        let gap = match (path.first(), path.last()) {
+            (Some(a), Some(b)) => ((a.0 - b.0) as f32).hypot((a.1 - b.1) as f32),
+            _ => 0.0,
+        };
+        Features {
+            points: path.len(),
+            length,
+            area: if path.is_empty() {
+                0.0
+            } else {
+                ((max.0 - min.0 + 1) * (max.1 - min.1 + 1)) as f32
+            },
+            strength,
+            border: border.max(0.0),
+            gap,
+            inside,
+        }
+    }
+}
+
+impl Criterion {
+    pub fn accepts(&self, f: &Features) -> bool {
+        match *self {
+            Criterion::Length { min, max } => f.length >= min && max.is_none_or(|m| f.length <= m),
+            Criterion::Area { min } => f.area >= min,
+            Criterion::Strength { min } => f.strength >= min,
+            Criterion::Border { min } => f.border >= min,
+            Criterion::Closed { max_gap } => f.gap <= max_gap,
+            Criterion::Inside { min } => f.inside >= min,
+        }
+    }
+}
+
+impl Ranking {
+    // whether the image gradient has to be computed
+    pub fn needs_strength(&self) -> bool {
+        matches!(self, Ranking::Saliency(s) if s.strength != 0.0)
+    }
+
+    // indices into `features`, the path to keep first first
+    pub fn order(&self, features: &[Features], context: &Context) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..features.len()).collect();
+        match self {
+            Ranking::Longest => order.sort_by_key(|&i| std::cmp::Reverse(features[i].points)),
+            Ranking::Saliency(weights) => {
+                let scores = weights.scores(features, context);
+                order.sort_by(|&a, &b| scores[b].total_cmp(&scores[a]).then(a.cmp(&b)));
+            }
+        }
+        order
+    }
+}
+
+impl Saliency {
+    pub fn scores(&self, features: &[Features], context: &Context) -> Vec<f32> {
+        let most = |feature: fn(&Features) -> f32| {
+            features.iter().map(feature).fold(f32::EPSILON, f32::max)
+        };
+        let (length, area, strength) = (most(|f| f.length), most(|f| f.area), most(|f| f.strength));
+        let margin = (context.width.min(context.height) as f32 / 10.0).max(1.0);
+        let total =
+            self.length + self.area + self.strength + self.border + self.closed + self.inside;
+        features
+            .iter()
+            .map(|f| {
+                let score = self.length * f.length / length
+                    + self.area * f.area / area
+                    + self.strength * f.strength / strength
+                    + self.border * (f.border / margin).min(1.0)
+                    + self.closed * (1.0 - f.gap / f.length.max(1.0)).max(0.0)
+                    + self.inside * f.inside;
+                score / total.max(f32::EPSILON)
+            })
+            .collect()
+    }
+}
+
+// the paths every criterion accepts, in the order of the ranking, at most `max` of them (0 for all)
+pub fn select(
+    paths: &[Path],
+    context: &Context,
+    criteria: &[Criterion],
+    ranking: &Ranking,
+    max: usize,
+    threads: usize,
+) -> Vec<Path> {
+    let features = parallel::map(paths, threads, |p| Features::of(p, context));
+    let kept: Vec<usize> = (0..paths.len())
+        .filter(|&i| criteria.iter().all(|c| c.accepts(&features[i])))
+        .collect();
+    let kept_features: Vec<Features> = kept.iter().map(|&i| features[i]).collect();
+    let mut order = ranking.order(&kept_features, context);
+    if max > 0 {
+        order.truncate(max);
+    }
+    order.into_iter().map(|i| paths[kept[i]].clone()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+
+    fn context(img: &DynamicImage) -> Context {
+        Context::new(img, true, None, 1).unwrap()
+    }
+
+    // a dark square on white, with the outline traced and a faint stroke next to it
+    fn image() -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(100, 100, |x, y| {
+            if (20..60).contains(&x) && (20..60).contains(&y) {
+                Rgba([0, 0, 0, 255])
+            } else if (80..83).contains(&x) && (10..90).contains(&y) {
+                Rgba([230, 230, 230, 255])
+            } else {
+                Rgba([255, 255, 255, 255])
+            }
+        }))
+    }
+
+    fn outline() -> Path {
+        let mut path: Path = (20..60).map(|x| (x, 20)).collect();
+        path.extend((21..60).map(|y| (59, y)));
+        path.extend((20..59).rev().map(|x| (x, 59)));
+        path.extend((21..59).rev().map(|y| (20, y)));
+        path
+    }
+
+    fn stroke() -> Path {
+        (10..90).map(|y| (80, y)).collect()
+    }
+
+    #[test]
+    fn test_features() {
+        let img = image();
+        let square = Features::of(&outline(), &context(&img));
+        assert_eq!(square.points, 156);
+        assert!((square.length - 155.0).abs() < 1e-3);
+        assert_eq!(square.area, 1600.0);
+        assert_eq!(square.border, 20.0);
+        assert!(square.gap < 1.5);
+
+        let line = Features::of(&stroke(), &context(&img));
+        assert_eq!((line.area, line.border, line.gap), (80.0, 10.0, 79.0));
+        assert!(square.strength > 4.0 * line.strength && line.strength > 0.0);
+
+        // a jump back does not add to the length
+        let jump: Path = vec![(0, 0), (1, 0), (2, 0), (0, 1), (1, 1)];
+        assert_eq!(Features::of(&jump, &context(&img)).length, 3.0);
+    }
+
+    #[test]
+    fn test_criteria_and_saliency() {
+        let img = image();
+        let context = context(&img);
+        // the bottom row, paths[2] with 200 points, is the longest, then the outline with 156
+        let paths = vec![
+            outline(),
+            stroke(),
+            (0..200).map(|x| (x % 100, 99)).collect(),
+        ];
+
+        let longest = select(&paths, &context, &[], &Ranking::Longest, 2, 1);
+        assert_eq!(longest, vec![paths[2].clone(), paths[0].clone()]);
+
+        let criteria = [
+            Criterion::Border { min: 1.0 },
+            Criterion::Length {
+                min: 50.0,
+                max: Some(100.0),
+            },
+        ];
+        assert_eq!(
+            select(&paths, &context, &criteria, &Ranking::Longest, 0, 1),
+            vec![paths[1].clone()]
+        );
+        let closed = [Criterion::Closed { max_gap: 2.0 }];
+        assert_eq!(
+            select(&paths, &context, &closed, &Ranking::Longest, 0, 1),
+            vec![paths[0].clone()]
+        );
+
+        // strong, closed and away from the border beats long
+        let salient = select(
+            &paths,
+            &context,
+            &[],
+            &Ranking::Saliency(Saliency::default()),
+            0,
+            1,
+        );
+        assert_eq!(salient[0], paths[0]);
+        assert_eq!(salient.len(), 3);
+    }
+}
@@ -1,53 +1,253 @@
+use serde::{Deserialize, Serialize};
 use std::f32::consts::PI;
 
+pub const MAX_FREQS: usize = 40;
+
+// tapers the higher harmonics to tame the overshoot a truncated series has next to sharp
+// features, at the cost of some sharpness. Fejér softens the most, Lanczos the least
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Window {
+    #[default]
+    None,
+    Lanczos, // sigma factors, sinc(k / (n + 1))
+    Fejer,   // Cesàro averaging of the partial sums, 1 - k / (n + 1)
+    Hann,    // raised cosine, (1 + cos(pi k / (n + 1))) / 2
+}
+
+impl Window {
+    // weight of harmonic k when the highest one is n
+    pub fn factor(self, k: f32, n: f32) -> f32 {
+        let x = k.abs() / (n + 1.0);
+        match self {
+            Window::None => 1.0,
+            Window::Lanczos if x == 0.0 => 1.0,
+            Window::Lanczos => (PI * x).sin() / (PI * x),
+            Window::Fejer => 1.0 - x,
+            Window::Hann => 0.5 * (1.0 + (PI * x).cos()),
+        }
+    }
+}
+
+// a single harmonic of the series, coefficients are already divided by the number of samples
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Term {
+    pub k: f32,
+    pub cx: f32,
+    pub cy: f32,
+}
+
+impl Term {
+    // its parts of the x and y expressions
+    pub fn to_equation_in(&self, var: &str) -> (String, String) {
+        let x = format!(
+            "{} cos({}{var}) - {} sin({}{var})",
+            self.cx,
+            self.k * PI,
+            self.cy,
+            self.k * PI
+        );
+        let y = format!(
+            "{} sin({}{var}) + {} cos({}{var})",
+            self.cx,
+            self.k * PI,
+            self.cy,
+            self.k * PI
+        );
+        (x, y)
+    }
+}
+
+// fitted series for one line, terms go from k = -freqs to k = freqs
+// the line is traced forwards for t in [0, 1) and backwards for t in [1, 2)
+#[derive(Debug, Clone, PartialEq)]
+pub struct FourierSeries {
+    pub terms: Vec<Term>, // as fitted, the window is only applied when evaluating and exporting
+    pub window: Window,
+}
+
+impl FourierSeries {
+    pub fn new(terms: Vec<Term>) -> FourierSeries {
+        FourierSeries {
+            terms,
+            window: Window::None,
+        }
+    }
+
+    pub fn with_window(mut self, window: Window) -> FourierSeries {
+        self.window = window;
+        self
+    }
+
+    // the terms with the window applied, what evaluate and to_equation use
+    pub fn windowed_terms(&self) -> Vec<Term> {
+        let n = self.highest_harmonic();
+        self.terms
+            .iter()
+            .map(|t| {
+                let f = self.window.factor(t.k, n);
+                Term {
+                    k: t.k,
+                    cx: t.cx * f,
+                    cy: t.cy * f,
+                }
+            })
+            .collect()
+    }
+
+    fn highest_harmonic(&self) -> f32 {
+        self.terms.iter().map(|t| t.k.abs()).fold(0.0, f32::max)
+    }
+
+    pub fn fit(points: &[(i32, i32)]) -> FourierSeries {
+        FourierSeries::fit_with(points, MAX_FREQS)
+    }
+
+    // like fit, but with at most max_freqs harmonics on either side of the constant term
+    pub fn fit_with(points: &[(i32, i32)], max_freqs: usize) -> FourierSeries {
+        let points: Vec<(f32, f32)> = points.iter().map(|&(x, y)| (x as f32, y as f32)).collect();
+        FourierSeries::fit_points(&points, max_freqs)
+    }
+
+    // fit_with for sub-pixel points, e.g. ones sampled from another curve
+    pub fn fit_points(points: &[(f32, f32)], max_freqs: usize) -> FourierSeries {
+        let freqs = std::cmp::min(points.len() / 2, max_freqs);
+
+        let mut new_points = points.to_vec();
+
+        for points in points.iter().rev() {
+            new_points.push((points.0, points.1));
+        }
+
+        let len = new_points.len() as f32;
+        let mut terms = Vec::with_capacity(freqs * 2 + 1);
+
+        for n in 0..(freqs * 2 + 1) {
+            let (mut cx, mut cy) = (0.0, 0.0);
+            let k = (n as f32) - (freqs as f32); // goes from -freqs to freqs
+            let pik2 = 2.0 * PI * k;
+
+            for (i, f_num) in new_points.iter().enumerate() {
+                let m = i as f32;
+                cx += (pik2 * m / len).cos() * f_num.0 + (pik2 * m / len).sin() * f_num.1;
+                cy += (pik2 * m / len).cos() * f_num.1 - (pik2 * m / len).sin() * f_num.0;
+            }
+
+            terms.push(Term {
+                k,
+                cx: cx / len,
+                cy: cy / len,
+            });
+        }
+        FourierSeries::new(terms)
+    }
+
+    // point on the curve in image coordinates (y pointing down), t in [0, 2)
+    pub fn evaluate(&self, t: f32) -> (f32, f32) {
+        let (mut x, mut y) = (0.0, 0.0);
+        let n = self.highest_harmonic();
+        for term in self.terms.iter() {
+            let (sin, cos) = (term.k * PI * t).sin_cos();
+            let f = self.window.factor(term.k, n);
+            let (cx, cy) = (term.cx * f, term.cy * f);
+            x += cx * cos - cy * sin;
+            y += cx * sin + cy * cos;
+        }
+        (x, y)
+    }
+
+    // n evenly spaced points along the forward half of the curve, i.e. the traced line itself
+    pub fn sample(&self, n: usize) -> Vec<(f32, f32)> {
+        let steps = n.max(2) - 1;
+        (0..=steps)
+            .map(|i| self.evaluate(i as f32 / steps as f32))
+            .collect()
+    }
+
+    // only the harmonics up to `harmonics`, the window then tapers towards the new highest one
+    pub fn truncated(&self, harmonics: usize) -> FourierSeries {
+        FourierSeries {
+            terms: self
+                .terms
+                .iter()
+                .filter(|t| t.k.abs() <= harmonics as f32)
+                .copied()
+                .collect(),
+            window: self.window,
+        }
+    }
+
+    // numbers it takes to write down, the two parts of every coefficient
+    pub fn parameters(&self) -> usize {
+        self.terms.len() * 2
+    }
+
+    pub fn to_equation(&self) -> String {
+        self.to_equation_in("t")
+    }
+
+    // the same expression in another variable, e.g. "((t-0.5)/0.25)" for a piece of a curve
+    pub fn to_equation_in(&self, var: &str) -> String {
+        let mut equation = String::new();
+        let mut xt = vec![];
+        let mut yt = vec![];
+
+        for term in self.windowed_terms().iter() {
+            let (x, y) = term.to_equation_in(var);
+            xt.push(x);
+            yt.push(y);
+        }
+        // println!("xt: {:?}", xt);
+        // println!("yt: {:?}", yt);
+        equation.push_str("((");
+        equation.push_str(&xt.join("+"));
+        equation.push_str("),-(");
+        equation.push_str(&yt.join("+"));
+        equation.push_str("))");
+        equation
+    }
+}
+
 pub fn construct_equation(points: &[(i32, i32)]) -> String {
-    let mut equation = String::new();
-    let mut xt = vec![];
-    let mut yt = vec![];
-
-    let freqs = std::cmp::min(points.len() / 2, 40);
-
-    let mut new_points = points.to_vec();
-
-    for points in points.iter().rev() {
-        new_points.push((points.0, points.1));
-    }
-
-    let len = new_points.len() as f32;
-
-    for n in 0..(freqs * 2 + 1) {
-        let (mut cx, mut cy) = (0.0, 0.0);
-        let k = (n as f32) - (freqs as f32); // goes from -freqs to freqs
-        let pik2 = 2.0 * PI * k;
-
-        for (i, p) in new_points.iter().enumerate() {
-            let f_num = (p.0 as f32, p.1 as f32);
-            let m = i as f32;
-            cx += (pik2 * m / len).cos() * f_num.0 + (pik2 * m / len).sin() * f_num.1;
-            cy += (pik2 * m / len).cos() * f_num.1 - (pik2 * m / len).sin() * f_num.0;
-        }
-
-        xt.push(format!(
-            "{} cos({}t) - {} sin({}t)",
-            cx / len,
-            k * PI,
-            cy / len,
-            k * PI
-        ));
-        yt.push(format!(
-            "{} sin({}t) + {} cos({}t)",
-            cx / len,
-            k * PI,
-            cy / len,
-            k * PI
-        ));
-    }
-    // println!("xt: {:?}", xt);
-    // println!("yt: {:?}", yt);
-    equation.push_str("((");
-    equation.push_str(&xt.join("+"));
-    equation.push_str("),-(");
-    equation.push_str(&yt.join("+"));
-    equation.push_str("))");
-    equation
+    FourierSeries::fit(points).to_equation()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evaluate_follows_line() {
+        let line: Vec<(i32, i32)> = (0..40).map(|i| (i, 2 * i)).collect();
+        let series = FourierSeries::fit(&line);
+        let (x, y) = series.evaluate(0.5);
+        assert!((x - 20.0).abs() < 1.0 && (y - 40.0).abs() < 2.0);
+    }
+
+    #[test]
+    fn test_sample_count() {
+        let line: Vec<(i32, i32)> = (0..20).map(|i| (i, 0)).collect();
+        assert_eq!(FourierSeries::fit(&line).sample(64).len(), 64);
+    }
+
+    #[test]
+    fn test_window_reduces_ringing() {
+        // a step, which the truncated series overshoots on both sides
+        let step: Vec<(i32, i32)> = (0..60).map(|i| (i, if i < 30 { 0 } else { 20 })).collect();
+        let series = FourierSeries::fit_with(&step, 10);
+        let overshoot = |s: &FourierSeries| {
+            let ys = s.sample(400).into_iter().map(|p| p.1);
+            ys.fold(0.0f32, |m, y| m.max(-y).max(y - 20.0))
+        };
+        let raw = overshoot(&series);
+        for window in [Window::Lanczos, Window::Hann, Window::Fejer] {
+            let windowed = series.clone().with_window(window);
+            assert_eq!(windowed.terms, series.terms);
+            assert!(overshoot(&windowed) < raw, "{window:?}");
+            assert_ne!(windowed.to_equation(), series.to_equation());
+        }
+        assert_eq!(Window::Hann.factor(0.0, 10.0), 1.0);
+        assert!(Window::Fejer.factor(10.0, 10.0) > 0.0);
+        assert_eq!(series.windowed_terms(), series.terms);
+    }
 }
@@ -1,11 +1,22 @@
+use crate::error::{Error, Result};
 use image::{DynamicImage, GenericImage, GenericImageView};
 use rand::Rng;
 
-static WHITE: image::Rgba<u8> = image::Rgba([255, 255, 255, 255]);
-static BLACK: image::Rgba<u8> = image::Rgba([0, 0, 0, 255]);
+pub static WHITE: image::Rgba<u8> = image::Rgba([255, 255, 255, 255]);
+pub static BLACK: image::Rgba<u8> = image::Rgba([0, 0, 0, 255]);
 
-pub fn get_image(path: &str) -> DynamicImage {
-    image::open(path).unwrap()
+// a traced line, in the order the pixels were visited
+pub type Path = Vec<(i32, i32)>;
+
+// shorter lines than this are dropped when tracing
+pub const MIN_PATH_LEN: usize = 16;
+
+pub fn get_image(path: &str) -> Result<DynamicImage> {
+    let img = image::open(path).map_err(Error::image(path))?;
+    if img.width() == 0 || img.height() == 0 {
+        return Err(Error::EmptyInput(format!("{path} has no pixels")));
+    }
+    Ok(img)
 } // fn get_image()
 
 fn first_col(img: &DynamicImage, col: image::Rgba<u8>) -> Option<(u32, u32)> {
@@ -119,7 +130,7 @@ fn remove_start_palindrome(path: &mut Vec<(i32, i32)>) {
     }
 }
 
-pub fn edges_to_lines(img: &mut DynamicImage, col: image::Rgba<u8>) -> Vec<Vec<(i32, i32)>> {
+pub fn edges_to_lines(img: &DynamicImage, col: image::Rgba<u8>, min_len: usize) -> Vec<Path> {
     let mut lines = vec![];
     let dims = img.dimensions();
     let mut visited = vec![false; (dims.0 * dims.1) as usize];
@@ -134,7 +145,7 @@ pub fn edges_to_lines(img: &mut DynamicImage, col: image::Rgba<u8>) -> Vec<Vec<(
                 // from the end of the path, then it can be partially truncated
                 remove_start_palindrome(&mut path);
                 remove_end_palindrome(&mut path);
-                if path.len() > 16 {
+                if path.len() > min_len {
                     lines.push(path);
                 }
             }
@@ -143,14 +154,44 @@ pub fn edges_to_lines(img: &mut DynamicImage, col: image::Rgba<u8>) -> Vec<Vec<(
     lines
 }
 
-#[allow(dead_code)]
-pub fn edges_to_lines_w(img: &mut DynamicImage) -> Vec<Vec<(i32, i32)>> {
-    edges_to_lines(img, WHITE)
+pub fn edges_to_lines_w(img: &DynamicImage) -> Vec<Path> {
+    edges_to_lines(img, WHITE, MIN_PATH_LEN)
 }
 
-#[allow(dead_code)]
-pub fn edges_to_lines_b(img: &mut DynamicImage) -> Vec<Vec<(i32, i32)>> {
-    edges_to_lines(img, BLACK)
+pub fn edges_to_lines_b(img: &DynamicImage) -> Vec<Path> {
+    edges_to_lines(img, BLACK, MIN_PATH_LEN)
+}
+
+fn perpendicular_distance(p: (i32, i32), a: (i32, i32), b: (i32, i32)) -> f32 {
+    let (dx, dy) = ((b.0 - a.0) as f32, (b.1 - a.1) as f32);
+    let len = (dx * dx + dy * dy).sqrt();
+    if len == 0.0 {
+        return (((p.0 - a.0).pow(2) + (p.1 - a.1).pow(2)) as f32).sqrt();
+    }
+    (dy * (p.0 - a.0) as f32 - dx * (p.1 - a.1) as f32).abs() / len
+}
+
+// Ramer-Douglas-Peucker, drops points that are within epsilon pixels of the simplified line
+pub fn simplify(path: &[(i32, i32)], epsilon: f32) -> Path {
+    if path.len() < 3 {
+        return path.to_vec();
+    }
+    let (first, last) = (path[0], path[path.len() - 1]);
+    let (index, max_dist) = path[1..path.len() - 1]
+        .iter()
+        .enumerate()
+        .map(|(i, &p)| (i + 1, perpendicular_distance(p, first, last)))
+        .fold((0, 0.0), |acc, x| if x.1 > acc.1 { x } else { acc });
+
+    if max_dist > epsilon {
+        let mut left = simplify(&path[..=index], epsilon);
+        let right = simplify(&path[index..], epsilon);
+        left.pop(); // shared point
+        left.extend(right);
+        left
+    } else {
+        vec![first, last]
+    }
 }
 
 fn random_col() -> image::Rgba<u8> {
@@ -166,7 +207,7 @@ pub fn line_to_img(img: &mut DynamicImage, line: &[(i32, i32)], col: image::Rgba
     }
 } // fn line_to_img()
 
-pub fn lines_to_img(lines: &[Vec<(i32, i32)>]) {
+pub fn lines_to_img(lines: &[Path], file: impl AsRef<std::path::Path>) -> Result<()> {
     let (mut max_x, mut max_y) = (0, 0);
     for line in lines.iter() {
         for point in line.iter() {
@@ -183,13 +224,19 @@ pub fn lines_to_img(lines: &[Vec<(i32, i32)>]) {
         let col = random_col();
         line_to_img(&mut img, line, col);
     }
-    img.save("generated/lines.png").unwrap();
+    img.save(&file).map_err(Error::image(&file))
 } // fn lines_to_img()
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_simplify() {
+        let path = vec![(0, 0), (1, 0), (2, 0), (3, 0), (3, 1), (3, 2), (3, 3)];
+        assert_eq!(simplify(&path, 0.5), vec![(0, 0), (3, 0), (3, 3)]);
+    }
+
     #[test]
     fn test_remove_end_palindrome() {
         let mut path1 = vec![(0, 0), (1, 1), (2, 2), (3, 3), (4, 4), (5, 5), (6, 6)];
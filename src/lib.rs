@@ -0,0 +1,32 @@
+// Converts lines from an image into parametric equations using Fourier transforms.
+// Pipeline chains the stages together, the modules can also be used on their own.
+pub mod audio;
+pub mod batch;
+pub mod bezier;
+pub mod budget;
+pub mod config;
+pub mod descriptors;
+pub mod draw;
+pub mod dxf;
+pub mod edge_detection;
+pub mod epicycles;
+pub mod error;
+pub mod filter;
+pub mod fourier;
+pub mod img_to_line;
+pub mod metrics;
+pub mod model;
+pub mod morph;
+pub mod parallel;
+pub mod piecewise;
+pub mod pipeline;
+pub mod plotter;
+pub mod render;
+pub mod shape_index;
+pub mod spline;
+pub mod svg;
+
+pub use error::{Error, Result};
+pub use fourier::FourierSeries;
+pub use img_to_line::Path;
+pub use pipeline::{Pipeline, PipelineResult};
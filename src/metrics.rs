@@ -0,0 +1,292 @@
+use crate::img_to_line::Path;
+use crate::model::CurveModel;
+use crate::render::{render_curves, RenderOptions};
+use image::{DynamicImage, GenericImageView, GrayImage, Luma, Rgba};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metrics {
+    pub hausdorff: f32,
+    pub chamfer: f32, // mean of the two directed mean distances
+    pub precision: f32,
+    pub recall: f32,
+    pub f1: f32,
+    pub tolerance: f32,
+    pub curve_rms: Vec<f32>, // one per curve, against the path it was fitted to
+}
+
+fn json_number(v: f32) -> String {
+    if v.is_finite() {
+        format!("{v}")
+    } else {
+        "null".to_string()
+    }
+}
+
+impl Metrics {
+    pub fn to_json(&self) -> String {
+        let rms = self
+            .curve_rms
+            .iter()
+            .map(|&v| json_number(v))
+            .collect::<Vec<_>>()
+            .join(", ");
+        format!(
+            "{{\n  \"hausdorff\": {},\n  \"chamfer\": {},\n  \"precision\": {},\n  \"recall\": {},\n  \"f1\": {},\n  \"tolerance\": {},\n  \"curve_rms\": [{}]\n}}\n",
+            json_number(self.hausdorff),
+            json_number(self.chamfer),
+            json_number(self.precision),
+            json_number(self.recall),
+            json_number(self.f1),
+            json_number(self.tolerance),
+            rms
+        )
+    }
+}
+
+// pixels of an edge image that have the traced colour
+pub fn edge_mask(img: &DynamicImage, col: Rgba<u8>) -> GrayImage {
+    let mut mask = GrayImage::new(img.width(), img.height());
+    for (x, y, p) in img.pixels() {
+        if p == col {
+            mask.put_pixel(x, y, Luma([255]));
+        }
+    }
+    mask
+}
+
+pub fn paths_mask((width, height): (u32, u32), paths: &[Path]) -> GrayImage {
+    let mut mask = GrayImage::new(width, height);
+    for &(x, y) in paths.iter().flatten() {
+        if x >= 0 && y >= 0 && (x as u32) < width && (y as u32) < height {
+            mask.put_pixel(x as u32, y as u32, Luma([255]));
+        }
+    }
+    mask
+}
+
+// rasterized curves, any pixel covered by at least half the stroke counts
+pub fn curves_mask(dims: (u32, u32), curves: &[impl CurveModel]) -> GrayImage {
+    let options = RenderOptions {
+        stroke: Rgba([255, 255, 255, 255]),
+        background: Rgba([0, 0, 0, 255]),
+        original_opacity: 0.0,
+        ..Default::default()
+    };
+    let img = render_curves(dims, curves, None, &options);
+    let mut mask = GrayImage::new(dims.0, dims.1);
+    for (x, y, p) in img.enumerate_pixels() {
+        if p[0] >= 128 {
+            mask.put_pixel(x, y, Luma([255]));
+        }
+    }
+    mask
+}
+
+// squared distance to the nearest zero of f, Felzenszwalb & Huttenlocher
+fn edt_1d(f: &[f32], d: &mut [f32]) {
+    let n = f.len();
+    if n == 0 {
+        return;
+    }
+    let mut v = vec![0; n];
+    let mut z = vec![0.0; n + 1];
+    let mut k = 0;
+    z[0] = f32::NEG_INFINITY;
+    z[1] = f32::INFINITY;
+    let parabola = |q: usize, p: usize| {
+        ((f[q] + (q * q) as f32) - (f[p] + (p * p) as f32)) / (2 * q - 2 * p) as f32
+    };
+    for q in 1..n {
+        let mut s = parabola(q, v[k]);
+        while s <= z[k] {
+            k -= 1;
+            s = parabola(q, v[k]);
+        }
+        k += 1;
+        v[k] = q;
+        z[k] = s;
+        z[k + 1] = f32::INFINITY;
+    }
+    k = 0;
+    for (q, dq) in d.iter_mut().enumerate() {
+        while z[k + 1] < q as f32 {
+            k += 1;
+        }
+        *dq = (q as f32 - v[k] as f32).powi(2) + f[v[k]];
+    }
+}
+
+// euclidean distance from every pixel to the nearest set pixel of the mask, row major
+pub fn distance_transform(mask: &GrayImage) -> Vec<f32> {
+    let (w, h) = (mask.width() as usize, mask.height() as usize);
+    // large but finite so the parabola intersections stay well defined
+    let far = ((w * w + h * h) as f32) * 4.0 + 1.0;
+    let mut grid: Vec<f32> = mask
+        .pixels()
+        .map(|p| if p[0] > 0 { 0.0 } else { far })
+        .collect();
+
+    let mut col = vec![0.0; h];
+    let mut out = vec![0.0; h];
+    for x in 0..w {
+        for y in 0..h {
+            col[y] = grid[y * w + x];
+        }
+        edt_1d(&col, &mut out);
+        for y in 0..h {
+            grid[y * w + x] = out[y];
+        }
+    }
+    let mut row = vec![0.0; w];
+    for y in 0..h {
+        row.copy_from_slice(&grid[y * w..(y + 1) * w]);
+        edt_1d(&row, &mut grid[y * w..(y + 1) * w]);
+    }
+    grid.iter()
+        .map(|&d| if d >= far { f32::INFINITY } else { d.sqrt() })
+        .collect()
+}
+
+// distances from every set pixel of `from` to the nearest set pixel of `to`
+fn directed(from: &GrayImage, to_dist: &[f32]) -> Vec<f32> {
+    from.pixels()
+        .zip(to_dist.iter())
+        .filter(|(p, _)| p[0] > 0)
+        .map(|(_, &d)| d)
+        .collect()
+}
+
+fn mean(v: &[f32]) -> f32 {
+    if v.is_empty() {
+        f32::INFINITY
+    } else {
+        v.iter().sum::<f32>() / v.len() as f32
+    }
+}
+
+// set based scores between a reference mask (e.g. the edge map) and a candidate mask
+pub fn compare(reference: &GrayImage, candidate: &GrayImage, tolerance: f32) -> Metrics {
+    let cand_to_ref = directed(candidate, &distance_transform(reference));
+    let ref_to_cand = directed(reference, &distance_transform(candidate));
+
+    let within = |v: &[f32]| {
+        if v.is_empty() {
+            0.0
+        } else {
+            v.iter().filter(|&&d| d <= tolerance).count() as f32 / v.len() as f32
+        }
+    };
+    let precision = within(&cand_to_ref);
+    let recall = within(&ref_to_cand);
+    let f1 = if precision + recall > 0.0 {
+        2.0 * precision * recall / (precision + recall)
+    } else {
+        0.0
+    };
+    let max = |v: &[f32]| v.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+    let hausdorff = if cand_to_ref.is_empty() || ref_to_cand.is_empty() {
+        f32::INFINITY
+    } else {
+        max(&cand_to_ref).max(max(&ref_to_cand))
+    };
+
+    Metrics {
+        hausdorff,
+        chamfer: (mean(&cand_to_ref) + mean(&ref_to_cand)) / 2.0,
+        precision,
+        recall,
+        f1,
+        tolerance,
+        curve_rms: vec![],
+    }
+}
+
+// rms distance between the curve and the path it was fitted to, point for point
+pub fn curve_rms(path: &[(i32, i32)], curve: &impl CurveModel) -> f32 {
+    if path.is_empty() {
+        return 0.0;
+    }
+    let len = path.len() as f32;
+    let sum: f32 = path
+        .iter()
+        .enumerate()
+        .map(|(i, &(x, y))| {
+            let (cx, cy) = curve.evaluate(i as f32 / len);
+            (cx - x as f32).powi(2) + (cy - y as f32).powi(2)
+        })
+        .sum();
+    (sum / len).sqrt()
+}
+
+// compare the rasterized curves with the edge image, and each curve with its traced path
+pub fn evaluate(
+    edges: &DynamicImage,
+    col: Rgba<u8>,
+    paths: &[Path],
+    curves: &[impl CurveModel],
+    tolerance: f32,
+) -> Metrics {
+    let reference = edge_mask(edges, col);
+    let candidate = curves_mask(edges.dimensions(), curves);
+    let mut metrics = compare(&reference, &candidate, tolerance);
+    metrics.curve_rms = paths
+        .iter()
+        .zip(curves.iter())
+        .map(|(p, c)| curve_rms(p, c))
+        .collect();
+    metrics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fourier::FourierSeries;
+
+    #[test]
+    fn test_distance_transform() {
+        let mut mask = GrayImage::new(5, 4);
+        mask.put_pixel(1, 1, Luma([255]));
+        let dist = distance_transform(&mask);
+        assert_eq!(dist[5 + 1], 0.0);
+        assert_eq!(dist[5 + 4], 3.0);
+        assert!((dist[3 * 5 + 4] - 13f32.sqrt()).abs() < 1e-5);
+        assert!(distance_transform(&GrayImage::new(3, 3))
+            .iter()
+            .all(|d| d.is_infinite()));
+    }
+
+    #[test]
+    fn test_compare_shifted_line() {
+        let line: Path = (2..18).map(|x| (x, 5)).collect();
+        let shifted: Path = (2..18).map(|x| (x, 8)).collect();
+        let reference = paths_mask((20, 20), std::slice::from_ref(&line));
+        let metrics = compare(&reference, &paths_mask((20, 20), &[shifted]), 2.0);
+        assert_eq!(metrics.hausdorff, 3.0);
+        assert_eq!(metrics.chamfer, 3.0);
+        assert_eq!(metrics.f1, 0.0);
+
+        let same = compare(&reference, &paths_mask((20, 20), &[line]), 1.0);
+        assert_eq!(
+            (same.hausdorff, same.precision, same.recall),
+            (0.0, 1.0, 1.0)
+        );
+    }
+
+    #[test]
+    fn test_evaluate_line() {
+        let line: Path = (5..45).map(|x| (x, 20)).collect();
+        let mut edges = DynamicImage::new_rgb8(50, 40);
+        for &(x, y) in line.iter() {
+            edges
+                .as_mut_rgb8()
+                .unwrap()
+                .put_pixel(x as u32, y as u32, image::Rgb([255; 3]));
+        }
+        let curve = FourierSeries::fit(&line);
+        let metrics = evaluate(&edges, Rgba([255; 4]), &[line], &[curve], 2.0);
+        assert!(metrics.recall > 0.9 && metrics.precision > 0.9);
+        assert_eq!(metrics.curve_rms.len(), 1);
+        assert!(metrics.curve_rms[0] < 2.0);
+        assert!(metrics.to_json().contains("\"curve_rms\": ["));
+    }
+}
@@ -0,0 +1,478 @@
+use crate::bezier::{self, BezierCurve};
+use crate::fourier::FourierSeries;
+use crate::img_to_line::Path;
+use crate::piecewise::{self, PiecewiseCurve};
+use crate::pipeline::FitOptions;
+use crate::spline::{self, Spline};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write as _;
+
+const ERROR_POINTS: usize = 2048;
+const LINE_STEPS: usize = 4; // points per pixel step when measuring against the path
+
+// What the exporters need from a fitted curve, so any backend can be drawn, plotted or written
+// out. t goes from 0 at the start of the path to 1 at its end.
+pub trait CurveModel {
+    type Options;
+
+    fn fit(path: &Path, options: &Self::Options) -> Self
+    where
+        Self: Sized;
+
+    fn evaluate(&self, t: f32) -> (f32, f32);
+
+    // numbers it takes to write down
+    fn parameters(&self) -> usize;
+
+    // Desmos expressions, y negated so the drawing is the right way up
+    fn to_equations(&self) -> Vec<String>;
+
+    fn sample(&self, n: usize) -> Vec<(f32, f32)> {
+        let steps = n.max(2) - 1;
+        (0..=steps)
+            .map(|i| self.evaluate(i as f32 / steps as f32))
+            .collect()
+    }
+
+    // rms distance from each point of the path to the closest point of the curve and from each
+    // point of the curve to the closest point of the path, so neither missing parts of the path
+    // nor loops and overshoot away from it go unnoticed. it does not depend on how the model
+    // spreads t along the path. long paths and curves are measured at every few points,
+    // ERROR_POINTS of each
+    fn error(&self, path: &[(i32, i32)]) -> f32 {
+        if path.is_empty() {
+            return 0.0;
+        }
+        let samples = self.sample((path.len() * 4).clamp(256, 1 << 16));
+        // the path as a line between its pixels rather than the pixels alone, so a curve running
+        // along it is not charged for passing between two of them. jumps are not part of the line
+        let mut line = vec![(path[0].0 as f32, path[0].1 as f32)];
+        for w in path.windows(2) {
+            let (a, b) = (
+                (w[0].0 as f32, w[0].1 as f32),
+                (w[1].0 as f32, w[1].1 as f32),
+            );
+            if (b.0 - a.0).abs() <= 1.0 && (b.1 - a.1).abs() <= 1.0 {
+                line.extend((1..LINE_STEPS).map(|i| {
+                    let u = i as f32 / LINE_STEPS as f32;
+                    (a.0 + u * (b.0 - a.0), a.1 + u * (b.1 - a.1))
+                }));
+            }
+            line.push(b);
+        }
+        let path: Vec<(f32, f32)> = path.iter().map(|&(x, y)| (x as f32, y as f32)).collect();
+        // mean squared distance from every few of `from` to the closest of `to`
+        let one_way = |from: &[(f32, f32)], to: &[(f32, f32)]| {
+            let grid = Grid::new(to);
+            let step = from.len().div_ceil(ERROR_POINTS);
+            let points: Vec<(f32, f32)> = from.iter().copied().step_by(step).collect();
+            let sum: f32 = points.iter().map(|&p| grid.nearest_squared(p)).sum();
+            sum / points.len() as f32
+        };
+        (0.5 * (one_way(&path, &samples) + one_way(&samples, &line))).sqrt()
+    }
+
+    // svg path data, sampled unless the model has something better
+    fn to_svg_path(&self, samples: usize) -> String {
+        let mut d = String::new();
+        for (i, (x, y)) in self.sample(samples).iter().enumerate() {
+            let cmd = if i == 0 { 'M' } else { 'L' };
+            let _ = write!(d, "{cmd}{x:.2} {y:.2} ");
+        }
+        d.trim_end().to_string()
+    }
+}
+
+// points bucketed into square cells over their bounding box, for the closest one to a point
+struct Grid {
+    origin: (f32, f32),
+    width: i32,
+    height: i32,
+    cells: Vec<Vec<(f32, f32)>>,
+}
+
+const CELL: f32 = 4.0;
+
+impl Grid {
+    fn new(points: &[(f32, f32)]) -> Grid {
+        let (min_x, max_x, min_y, max_y) = points.iter().fold(
+            (f32::MAX, f32::MIN, f32::MAX, f32::MIN),
+            |(a, b, c, d), &(x, y)| (a.min(x), b.max(x), c.min(y), d.max(y)),
+        );
+        let width = ((max_x - min_x) / CELL) as i32 + 1;
+        let height = ((max_y - min_y) / CELL) as i32 + 1;
+        let mut grid = Grid {
+            origin: (min_x, min_y),
+            width,
+            height,
+            cells: vec![vec![]; (width * height) as usize],
+        };
+        for &p in points.iter() {
+            let (x, y) = grid.cell(p);
+            grid.cells[(y * width + x) as usize].push(p);
+        }
+        grid
+    }
+
+    fn cell(&self, p: (f32, f32)) -> (i32, i32) {
+        (
+            ((p.0 - self.origin.0) / CELL).floor() as i32,
+            ((p.1 - self.origin.1) / CELL).floor() as i32,
+        )
+    }
+
+    // searches rings of cells outwards until the next ring is further away than the best so far
+    fn nearest_squared(&self, p: (f32, f32)) -> f32 {
+        let (cx, cy) = self.cell(p);
+        let mut best = f32::INFINITY;
+        let furthest = cx.abs().max(cy.abs()) + self.width.max(self.height);
+        for r in 0..=furthest {
+            // every point of ring r is at least r - 1 cells away
+            if ((r - 1).max(0) as f32 * CELL).powi(2) >= best {
+                break;
+            }
+            for y in (cy - r).max(0)..=(cy + r).min(self.height - 1) {
+                // the whole row on the top and bottom of the ring, only its ends in between
+                let xs: Vec<i32> = if (y - cy).abs() == r {
+                    ((cx - r).max(0)..=(cx + r).min(self.width - 1)).collect()
+                } else {
+                    vec![cx - r, cx + r]
+                };
+                for x in xs.into_iter().filter(|&x| x >= 0 && x < self.width) {
+                    for q in self.cells[(y * self.width + x) as usize].iter() {
+                        best = best.min((q.0 - p.0).powi(2) + (q.1 - p.1).powi(2));
+                    }
+                }
+            }
+        }
+        best
+    }
+}
+
+impl CurveModel for FourierSeries {
+    type Options = FitOptions; // max_harmonics and window, the series' own part of it
+
+    fn fit(path: &Path, options: &FitOptions) -> Self {
+        FourierSeries::fit_with(path, options.max_harmonics).with_window(options.window)
+    }
+
+    fn evaluate(&self, t: f32) -> (f32, f32) {
+        self.evaluate(t)
+    }
+
+    fn parameters(&self) -> usize {
+        self.parameters()
+    }
+
+    fn to_equations(&self) -> Vec<String> {
+        vec![self.to_equation()]
+    }
+}
+
+impl CurveModel for PiecewiseCurve {
+    type Options = piecewise::PiecewiseOptions;
+
+    // corners by turning angle, the Harris detector needs the image
+    fn fit(path: &Path, options: &Self::Options) -> Self {
+        piecewise::fit(path, None, options)
+    }
+
+    fn evaluate(&self, t: f32) -> (f32, f32) {
+        self.evaluate(t)
+    }
+
+    fn parameters(&self) -> usize {
+        self.parameters()
+    }
+
+    fn to_equations(&self) -> Vec<String> {
+        self.to_equations()
+    }
+}
+
+impl CurveModel for BezierCurve {
+    type Options = bezier::BezierOptions;
+
+    fn fit(path: &Path, options: &Self::Options) -> Self {
+        bezier::fit(path, options)
+    }
+
+    fn evaluate(&self, t: f32) -> (f32, f32) {
+        self.evaluate(t)
+    }
+
+    fn parameters(&self) -> usize {
+        self.parameters()
+    }
+
+    fn to_equations(&self) -> Vec<String> {
+        self.to_equations()
+    }
+
+    fn to_svg_path(&self, _samples: usize) -> String {
+        self.to_svg_path()
+    }
+}
+
+impl CurveModel for Spline {
+    type Options = spline::SplineOptions;
+
+    fn fit(path: &Path, options: &Self::Options) -> Self {
+        spline::fit(path, options)
+    }
+
+    fn evaluate(&self, t: f32) -> (f32, f32) {
+        self.evaluate(t)
+    }
+
+    fn parameters(&self) -> usize {
+        self.parameters()
+    }
+
+    fn to_equations(&self) -> Vec<String> {
+        self.to_equations()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ModelKind {
+    Fourier,
+    Piecewise,
+    Bezier,
+    Spline,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SelectOptions {
+    pub candidates: Vec<ModelKind>,
+    pub lambda: f32, // pixels of rms error a parameter has to save to be worth writing down
+}
+
+impl Default for SelectOptions {
+    fn default() -> Self {
+        SelectOptions {
+            candidates: vec![
+                ModelKind::Fourier,
+                ModelKind::Piecewise,
+                ModelKind::Bezier,
+                ModelKind::Spline,
+            ],
+            lambda: 0.01,
+        }
+    }
+}
+
+// whichever backend a path ended up with
+#[derive(Debug, Clone, PartialEq)]
+pub enum Model {
+    Fourier(FourierSeries),
+    Piecewise(PiecewiseCurve),
+    Bezier(BezierCurve),
+    Spline(Spline),
+}
+
+impl Model {
+    pub fn kind(&self) -> ModelKind {
+        match self {
+            Model::Fourier(_) => ModelKind::Fourier,
+            Model::Piecewise(_) => ModelKind::Piecewise,
+            Model::Bezier(_) => ModelKind::Bezier,
+            Model::Spline(_) => ModelKind::Spline,
+        }
+    }
+
+    // fitted with the options FitOptions has for that backend, or its defaults
+    pub fn fit_kind(kind: ModelKind, path: &Path, options: &FitOptions) -> Model {
+        match kind {
+            ModelKind::Fourier => Model::Fourier(CurveModel::fit(path, options)),
+            ModelKind::Piecewise => Model::Piecewise(PiecewiseCurve::fit(
+                path,
+                &options.piecewise.clone().unwrap_or_default(),
+            )),
+            ModelKind::Bezier => Model::Bezier(BezierCurve::fit(
+                path,
+                &options.bezier.clone().unwrap_or_default(),
+            )),
+            ModelKind::Spline => Model::Spline(Spline::fit(
+                path,
+                &options.spline.clone().unwrap_or_default(),
+            )),
+        }
+    }
+
+    // error plus lambda per parameter, lower is better
+    pub fn cost(&self, path: &Path, lambda: f32) -> f32 {
+        self.error(path) + lambda * self.parameters() as f32
+    }
+}
+
+// the candidate with the lowest cost, the first one on a tie
+pub fn best(path: &Path, candidates: Vec<Model>, lambda: f32) -> Option<Model> {
+    candidates
+        .into_iter()
+        .map(|m| (m.cost(path, lambda), m))
+        .reduce(|a, b| if b.0 < a.0 { b } else { a })
+        .map(|(_, m)| m)
+}
+
+impl CurveModel for Model {
+    type Options = FitOptions;
+
+    // every candidate of options.select (just the Fourier series without it), keeping the best
+    fn fit(path: &Path, options: &FitOptions) -> Self {
+        let select = options.select.clone().unwrap_or(SelectOptions {
+            candidates: vec![ModelKind::Fourier],
+            ..Default::default()
+        });
+        let candidates = select
+            .candidates
+            .iter()
+            .map(|&kind| Model::fit_kind(kind, path, options))
+            .collect();
+        best(path, candidates, select.lambda)
+            .unwrap_or_else(|| Model::fit_kind(ModelKind::Fourier, path, options))
+    }
+
+    fn evaluate(&self, t: f32) -> (f32, f32) {
+        match self {
+            Model::Fourier(m) => m.evaluate(t),
+            Model::Piecewise(m) => m.evaluate(t),
+            Model::Bezier(m) => m.evaluate(t),
+            Model::Spline(m) => m.evaluate(t),
+        }
+    }
+
+    fn parameters(&self) -> usize {
+        match self {
+            Model::Fourier(m) => m.parameters(),
+            Model::Piecewise(m) => m.parameters(),
+            Model::Bezier(m) => m.parameters(),
+            Model::Spline(m) => m.parameters(),
+        }
+    }
+
+    fn to_equations(&self) -> Vec<String> {
+        match self {
+            Model::Fourier(m) => vec![m.to_equation()],
+            Model::Piecewise(m) => m.to_equations(),
+            Model::Bezier(m) => m.to_equations(),
+            Model::Spline(m) => m.to_equations(),
+        }
+    }
+
+    fn to_svg_path(&self, samples: usize) -> String {
+        match self {
+            Model::Fourier(m) => CurveModel::to_svg_path(m, samples),
+            Model::Piecewise(m) => CurveModel::to_svg_path(m, samples),
+            Model::Bezier(m) => m.to_svg_path(),
+            Model::Spline(m) => CurveModel::to_svg_path(m, samples),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fourier::Window;
+    use crate::piecewise::square;
+
+    // works the same for any backend
+    fn check<M: CurveModel>(path: &Path, options: &M::Options) -> (f32, usize) {
+        let model = M::fit(path, options);
+        let start = model.evaluate(0.0);
+        assert!((start.0 - path[0].0 as f32).hypot(start.1 - path[0].1 as f32) < 3.0);
+        assert!(!model.to_equations().is_empty());
+        assert!(model.to_svg_path(64).starts_with('M'));
+        (model.error(path), model.parameters())
+    }
+
+    #[test]
+    fn test_backends_through_trait() {
+        let path = square(30);
+        let (fourier_error, fourier_parameters) = check::<FourierSeries>(
+            &path,
+            &FitOptions {
+                max_harmonics: 10,
+                ..Default::default()
+            },
+        );
+        let (piecewise_error, piecewise_parameters) =
+            check::<PiecewiseCurve>(&path, &Default::default());
+        check::<BezierCurve>(&path, &Default::default());
+        check::<Spline>(&path, &Default::default());
+        assert!(piecewise_error < 0.1 && piecewise_error < fourier_error);
+        assert!(piecewise_parameters < fourier_parameters);
+    }
+
+    #[test]
+    fn test_error_ignores_parameterisation() {
+        let line: Path = (0..50).map(|i| (i, 0)).collect();
+        // the same segment, traced at a very uneven speed
+        let bezier = BezierCurve {
+            segments: vec![[(0.0, 0.0), (0.0, 0.0), (0.0, 0.0), (49.0, 0.0)]],
+        };
+        assert!(bezier.error(&line) < 0.1);
+        let off = BezierCurve {
+            segments: vec![[(0.0, 3.0), (10.0, 3.0), (30.0, 3.0), (49.0, 3.0)]],
+        };
+        assert!((off.error(&line) - 3.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_error_sees_overshoot() {
+        let line: Path = (0..50).map(|i| (i, 0)).collect();
+        let exact = BezierCurve {
+            segments: vec![[(0.0, 0.0), (16.0, 0.0), (33.0, 0.0), (49.0, 0.0)]],
+        };
+        // passes through every point of the line, then loops 20 pixels away from it
+        let mut looped = exact.clone();
+        looped
+            .segments
+            .push([(49.0, 0.0), (60.0, 20.0), (40.0, 20.0), (49.0, 0.0)]);
+        assert!(exact.error(&line) < 0.1);
+        assert!(looped.error(&line) > 5.0, "{}", looped.error(&line));
+    }
+
+    #[test]
+    fn test_fourier_fits_agree() {
+        let options = FitOptions {
+            window: Window::Lanczos,
+            ..Default::default()
+        };
+        let series = <FourierSeries as CurveModel>::fit(&square(20), &options);
+        assert_eq!(series.window, Window::Lanczos);
+        assert_eq!(
+            Model::fit_kind(ModelKind::Fourier, &square(20), &options),
+            Model::Fourier(series)
+        );
+    }
+
+    #[test]
+    fn test_select_best() {
+        let options = FitOptions {
+            select: Some(SelectOptions::default()),
+            ..Default::default()
+        };
+        // straight sides go to a model with sharp corners and few parameters
+        let model = Model::fit(&square(30), &options);
+        assert!(matches!(
+            model.kind(),
+            ModelKind::Piecewise | ModelKind::Bezier
+        ));
+        // without a selection every path is a Fourier series
+        let model = Model::fit(&square(30), &FitOptions::default());
+        assert_eq!(model.kind(), ModelKind::Fourier);
+        // a huge lambda picks the fewest parameters whatever the error
+        let few = best(
+            &square(30),
+            vec![
+                Model::fit_kind(ModelKind::Fourier, &square(30), &options),
+                Model::fit_kind(ModelKind::Piecewise, &square(30), &options),
+            ],
+            1e6,
+        );
+        assert_eq!(few.unwrap().kind(), ModelKind::Piecewise);
+    }
+}
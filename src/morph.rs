@@ -0,0 +1,452 @@
+use crate::epicycles;
+use crate::error::{Error, Result};
+use crate::fourier::{FourierSeries, Term};
+use crate::render::{self, RenderOptions};
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::f32::consts::PI;
+
+// points per curve when lining up the start of two closed curves
+const ALIGN_SAMPLES: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Interpolation {
+    Linear,
+    Spherical, // each harmonic's radius linearly and its phase along the shorter arc
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MorphOptions {
+    pub interpolation: Interpolation,
+    pub align: bool, // match start point, direction and orientation of the target first
+    pub frames: usize,
+    pub frame_delay_ms: u32,
+    pub bounce: bool, // morph back again, so the animation loops
+}
+
+impl Default for MorphOptions {
+    fn default() -> Self {
+        MorphOptions {
+            interpolation: Interpolation::Linear,
+            align: true,
+            frames: 60,
+            frame_delay_ms: 40,
+            bounce: true,
+        }
+    }
+}
+
+type Complex = (f32, f32);
+
+fn mul(a: Complex, b: Complex) -> Complex {
+    (a.0 * b.0 - a.1 * b.1, a.0 * b.1 + a.1 * b.0)
+}
+
+fn conj(a: Complex) -> Complex {
+    (a.0, -a.1)
+}
+
+fn polar(angle: f32) -> Complex {
+    let (sin, cos) = angle.sin_cos();
+    (cos, sin)
+}
+
+// one curve turning into another, for s from 0 to 1
+#[derive(Debug, Clone)]
+pub struct Morph {
+    terms: Vec<(f32, Complex, Complex)>, // k, coefficient at s = 0 and at s = 1 turned by -angle
+    angle: f32,                          // the target's rotation relative to the source
+    interpolation: Interpolation,
+}
+
+fn coefficients(series: &FourierSeries) -> BTreeMap<i32, Complex> {
+    series
+        .windowed_terms()
+        .iter()
+        .map(|t| (t.k as i32, (t.cx, t.cy)))
+        .collect()
+}
+
+fn max_harmonic(series: &FourierSeries) -> usize {
+    series
+        .terms
+        .iter()
+        .map(|t| t.k.abs() as usize)
+        .max()
+        .unwrap_or(0)
+}
+
+fn is_closed(points: &[(f32, f32)]) -> bool {
+    match (points.first(), points.last()) {
+        (Some(a), Some(b)) => (a.0 - b.0).hypot(a.1 - b.1) <= 2.0,
+        _ => false,
+    }
+}
+
+fn centred(points: &[(f32, f32)]) -> Vec<Complex> {
+    let n = points.len() as f32;
+    let (sx, sy) = points
+        .iter()
+        .fold((0.0, 0.0), |s, p| (s.0 + p.0, s.1 + p.1));
+    points
+        .iter()
+        .map(|p| (p.0 - sx / n, p.1 - sy / n))
+        .collect()
+}
+
+// a closed target refitted to start where the source starts and to go round the same way, found
+// by the cyclic shift whose best rotation fits the source's outline closest
+fn align_start(from: &FourierSeries, to: &FourierSeries) -> Option<FourierSeries> {
+    let a = from.sample(ALIGN_SAMPLES);
+    let b = to.sample(ALIGN_SAMPLES);
+    if !is_closed(&a) || !is_closed(&b) {
+        return None;
+    }
+    let n = ALIGN_SAMPLES - 1; // the last sample is back at the start
+    let (ca, cb) = (centred(&a[..n]), centred(&b[..n]));
+    let mut best = (0.0, 0, false);
+    for reversed in [false, true] {
+        for shift in 0..n {
+            let mut sum = (0.0, 0.0);
+            for (i, &p) in ca.iter().enumerate() {
+                let j = if reversed {
+                    (shift + n - i) % n
+                } else {
+                    (shift + i) % n
+                };
+                let q = mul(p, conj(cb[j]));
+                sum = (sum.0 + q.0, sum.1 + q.1);
+            }
+            let score = sum.0.hypot(sum.1);
+            if score > best.0 {
+                best = (score, shift, reversed);
+            }
+        }
+    }
+    let (_, shift, reversed) = best;
+    let mut points: Vec<(f32, f32)> = (0..=n)
+        .map(|i| {
+            let j = if reversed {
+                (shift + n - i % n) % n
+            } else {
+                (shift + i) % n
+            };
+            b[j]
+        })
+        .collect();
+    points.dedup();
+    Some(FourierSeries::fit_points(&points, max_harmonic(to)))
+}
+
+impl Morph {
+    pub fn new(from: &FourierSeries, to: &FourierSeries, options: &MorphOptions) -> Morph {
+        let mut to = to.clone();
+        if options.align {
+            if let Some(aligned) = align_start(from, &to) {
+                to = aligned;
+            }
+        }
+        let a = coefficients(from);
+        let mut b = coefficients(&to);
+
+        // the rotation taking the target closest to the source, the constant term is where the
+        // curve is rather than its shape so it is left out
+        let mut angle = 0.0;
+        if options.align {
+            let inner = |b: &BTreeMap<i32, Complex>| {
+                a.iter()
+                    .filter(|(k, _)| **k != 0)
+                    .filter_map(|(k, &ca)| b.get(k).map(|&cb| mul(ca, conj(cb))))
+                    .fold((0.0, 0.0), |s: Complex, q| (s.0 + q.0, s.1 + q.1))
+            };
+            // starting from the other end of an open curve is half a period along, which
+            // flips the sign of the odd harmonics
+            let flipped: BTreeMap<i32, Complex> = b
+                .iter()
+                .map(|(&k, &c)| (k, if k % 2 == 0 { c } else { (-c.0, -c.1) }))
+                .collect();
+            // a symmetric shape fits as well either way, e.g. a line reversed or turned half
+            // round, then the smaller turn wins
+            let (kept, other) = (inner(&b), inner(&flipped));
+            let (kept_angle, other_angle) = (kept.1.atan2(kept.0), other.1.atan2(other.0));
+            let (kept_score, other_score) = (kept.0.hypot(kept.1), other.0.hypot(other.1));
+            let tie = (other_score - kept_score).abs() <= 1e-3 * kept_score.max(other_score);
+            angle = kept_angle;
+            if (tie && other_angle.abs() < kept_angle.abs()) || (!tie && other_score > kept_score) {
+                b = flipped;
+                angle = other_angle;
+            }
+        }
+
+        let keys: std::collections::BTreeSet<i32> = a.keys().chain(b.keys()).copied().collect();
+        let turn = polar(angle);
+        let terms = keys
+            .into_iter()
+            .map(|k| {
+                let ca = a.get(&k).copied().unwrap_or_default();
+                let cb = b.get(&k).copied().unwrap_or_default();
+                let cb = if k == 0 { cb } else { mul(cb, turn) };
+                (k as f32, ca, cb)
+            })
+            .collect();
+        Morph {
+            terms,
+            angle,
+            interpolation: options.interpolation,
+        }
+    }
+
+    // radius and phase at both ends, with the phase change along the shorter arc
+    fn polar_terms(&self) -> impl Iterator<Item = (f32, (f32, f32), (f32, f32))> + '_ {
+        self.terms.iter().map(|&(k, a, b)| {
+            let (ra, rb) = (a.0.hypot(a.1), b.0.hypot(b.1));
+            let mut pa = a.1.atan2(a.0);
+            let mut pb = b.1.atan2(b.0);
+            if ra == 0.0 {
+                pa = pb;
+            } else if rb == 0.0 {
+                pb = pa;
+            }
+            let delta = (pb - pa + PI).rem_euclid(2.0 * PI) - PI;
+            (k, (ra, rb - ra), (pa, delta))
+        })
+    }
+
+    pub fn at(&self, s: f32) -> FourierSeries {
+        let mut terms: Vec<Term> = match self.interpolation {
+            Interpolation::Linear => self
+                .terms
+                .iter()
+                .map(|&(k, a, b)| {
+                    let (cx, cy) = (a.0 + (b.0 - a.0) * s, a.1 + (b.1 - a.1) * s);
+                    Term { k, cx, cy }
+                })
+                .collect(),
+            Interpolation::Spherical => self
+                .polar_terms()
+                .map(|(k, (r, dr), (p, dp))| {
+                    let (cx, cy) = polar(p + dp * s);
+                    let r = r + dr * s;
+                    Term {
+                        k,
+                        cx: r * cx,
+                        cy: r * cy,
+                    }
+                })
+                .collect(),
+        };
+        let turn = polar(-self.angle * s);
+        for term in terms.iter_mut().filter(|t| t.k != 0.0) {
+            (term.cx, term.cy) = mul((term.cx, term.cy), turn);
+        }
+        FourierSeries::new(terms)
+    }
+
+    // Desmos expression in t with a slider s, in the form FourierSeries::to_equation uses
+    pub fn to_equation(&self) -> String {
+        let mut xt = vec![];
+        let mut yt = vec![];
+        let turn = -self.angle;
+        match self.interpolation {
+            Interpolation::Linear => {
+                for &(k, a, b) in self.terms.iter() {
+                    let (x, y) = (
+                        format!("({}+{}s)", a.0, b.0 - a.0),
+                        format!("({}+{}s)", a.1, b.1 - a.1),
+                    );
+                    if k == 0.0 {
+                        xt.push(x);
+                        yt.push(y);
+                        continue;
+                    }
+                    let arg = format!("{}t+{}s", k * PI, turn);
+                    xt.push(format!("{x} cos({arg}) - {y} sin({arg})"));
+                    yt.push(format!("{x} sin({arg}) + {y} cos({arg})"));
+                }
+            }
+            Interpolation::Spherical => {
+                for (k, (r, dr), (p, dp)) in self.polar_terms() {
+                    let dp = if k == 0.0 { dp } else { dp + turn };
+                    let arg = format!("{}t+{}+{}s", k * PI, p, dp);
+                    xt.push(format!("({}+{}s) cos({arg})", r, dr));
+                    yt.push(format!("({}+{}s) sin({arg})", r, dr));
+                }
+            }
+        }
+        format!("(({}),-({}))", xt.join("+"), yt.join("+"))
+    }
+}
+
+// the centre of a curve, what a curve without a partner shrinks into or grows out of
+fn point(series: &FourierSeries) -> FourierSeries {
+    let terms = series
+        .terms
+        .iter()
+        .filter(|t| t.k == 0.0)
+        .copied()
+        .collect();
+    FourierSeries::new(terms)
+}
+
+// pairs the curves in order, from[i] with to[i]. sort both the same way first, e.g. by path
+// length so the longest turns into the longest, the filter ranking may have ordered them otherwise
+pub fn morph_curves(
+    from: &[FourierSeries],
+    to: &[FourierSeries],
+    options: &MorphOptions,
+) -> Vec<Morph> {
+    (0..from.len().max(to.len()))
+        .map(|i| match (from.get(i), to.get(i)) {
+            (Some(a), Some(b)) => Morph::new(a, b, options),
+            (Some(a), None) => Morph::new(a, &point(a), options),
+            (None, Some(b)) => Morph::new(&point(b), b, options),
+            (None, None) => unreachable!(),
+        })
+        .collect()
+}
+
+// s for each frame, from 0 to 1 and back again when bouncing
+fn frame_times(options: &MorphOptions) -> Vec<f32> {
+    let steps = options.frames.max(2) - 1;
+    let mut times: Vec<f32> = (0..=steps).map(|i| i as f32 / steps as f32).collect();
+    if options.bounce {
+        let back: Vec<f32> = times[1..steps].iter().rev().copied().collect();
+        times.extend(back);
+    }
+    times
+}
+
+pub fn render_frames(
+    morphs: &[Morph],
+    dims: (u32, u32),
+    options: &MorphOptions,
+    render: &RenderOptions,
+) -> Vec<RgbaImage> {
+    frame_times(options)
+        .into_iter()
+        .map(|s| {
+            let curves: Vec<FourierSeries> = morphs.iter().map(|m| m.at(s)).collect();
+            render::render_curves(dims, &curves, None, render)
+        })
+        .collect()
+}
+
+pub fn save_gif(
+    file: impl AsRef<std::path::Path>,
+    morphs: &[Morph],
+    dims: (u32, u32),
+    options: &MorphOptions,
+    render: &RenderOptions,
+) -> Result<()> {
+    let frames = render_frames(morphs, dims, options, render);
+    epicycles::save_gif(file, frames, options.frame_delay_ms)
+}
+
+// the slider first, then one expression per curve
+pub fn save_equations(file: impl AsRef<std::path::Path>, morphs: &[Morph]) -> Result<()> {
+    let mut text = String::from("s=0\n");
+    for morph in morphs.iter() {
+        text.push_str(&morph.to_equation());
+        text.push('\n');
+    }
+    std::fs::write(&file, text).map_err(Error::io(&file))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ellipse(start: f32, rx: f32, ry: f32) -> FourierSeries {
+        let path: Vec<(i32, i32)> = (0..=80)
+            .map(|i| {
+                let a = start + 2.0 * PI * i as f32 / 80.0;
+                (
+                    (60.0 + rx * a.cos()).round() as i32,
+                    (50.0 + ry * a.sin()).round() as i32,
+                )
+            })
+            .collect();
+        FourierSeries::fit_with(&path, 20)
+    }
+
+    fn nearest(p: (f32, f32), points: &[(f32, f32)]) -> f32 {
+        points
+            .iter()
+            .map(|q| (p.0 - q.0).hypot(p.1 - q.1))
+            .fold(f32::INFINITY, f32::min)
+    }
+
+    #[test]
+    fn test_ends_match_curves() {
+        let (a, b) = (ellipse(0.0, 30.0, 20.0), ellipse(2.0, 10.0, 25.0));
+        for interpolation in [Interpolation::Linear, Interpolation::Spherical] {
+            let options = MorphOptions {
+                interpolation,
+                ..Default::default()
+            };
+            let morph = Morph::new(&a, &b, &options);
+            let start = morph.at(0.0).sample(100);
+            for (p, q) in start.iter().zip(a.sample(100).iter()) {
+                assert!((p.0 - q.0).abs() < 1e-2 && (p.1 - q.1).abs() < 1e-2);
+            }
+            // the end is the target, started elsewhere
+            let target = b.sample(400);
+            assert!(morph
+                .at(1.0)
+                .sample(100)
+                .iter()
+                .all(|&p| nearest(p, &target) < 1.5));
+            let equation = morph.to_equation();
+            assert!(equation.starts_with("((") && equation.contains("s) cos("));
+        }
+    }
+
+    #[test]
+    fn test_aligned_line_does_not_collapse() {
+        // the same line drawn the other way round only has to stay put
+        let forwards: Vec<(i32, i32)> = (0..40).map(|i| (i, 10)).collect();
+        let backwards: Vec<(i32, i32)> = forwards.iter().rev().copied().collect();
+        let (a, b) = (
+            FourierSeries::fit(&forwards),
+            FourierSeries::fit(&backwards),
+        );
+
+        let aligned = Morph::new(&a, &b, &MorphOptions::default()).at(0.5);
+        let (x0, _) = aligned.evaluate(0.0);
+        let (x1, _) = aligned.evaluate(1.0);
+        assert!((x1 - x0).abs() > 35.0);
+
+        let options = MorphOptions {
+            align: false,
+            ..Default::default()
+        };
+        let unaligned = Morph::new(&a, &b, &options).at(0.5);
+        let (x0, _) = unaligned.evaluate(0.0);
+        let (x1, _) = unaligned.evaluate(1.0);
+        assert!((x1 - x0).abs() < 5.0);
+    }
+
+    #[test]
+    fn test_unpaired_curves_shrink() {
+        let a = ellipse(0.0, 30.0, 20.0);
+        let morphs = morph_curves(
+            &[a.clone(), a],
+            &[ellipse(1.0, 5.0, 5.0)],
+            &Default::default(),
+        );
+        assert_eq!(morphs.len(), 2);
+        let end = morphs[1].at(1.0).sample(10);
+        assert!(end.iter().all(|p| nearest(*p, &[(60.0, 50.0)]) < 1.0));
+        let options = MorphOptions {
+            frames: 5,
+            ..Default::default()
+        };
+        assert_eq!(
+            frame_times(&options),
+            [0.0, 0.25, 0.5, 0.75, 1.0, 0.75, 0.5, 0.25]
+        );
+    }
+}
@@ -0,0 +1,384 @@
+use crate::edge_detection::{
+    self, compass_angle, edge_direction, edge_magnitude, pixel_dir_offsets, GAUSSIAN_5X5, SOBEL_X,
+    SOBEL_Y,
+};
+use crate::error::Result;
+use image::{
+    DynamicImage, GenericImage, GenericImageView, GrayImage, ImageBuffer, Luma, Rgb, RgbImage,
+    Rgba, RgbaImage,
+};
+use num::integer::Roots;
+use std::ops::{AddAssign, Mul};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+// The edge detection stages on contiguous buffers, each output split into bands of rows that
+// scoped threads fill in. Every function returns exactly what its per-pixel counterpart in
+// edge_detection returns (same float operations in the same order), those stay as the reference.
+
+// one channel, 8 bit values stored as f32
+pub type Plane = ImageBuffer<Luma<f32>, Vec<f32>>;
+
+// 0 for one per core
+pub fn thread_count(threads: usize) -> usize {
+    match threads {
+        0 => thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    }
+}
+
+// a new plane, `row(y, out)` fills in row y. rows are split into one band per thread
+pub fn map_rows(
+    width: u32,
+    height: u32,
+    threads: usize,
+    row: impl Fn(usize, &mut [f32]) + Sync,
+) -> Plane {
+    let (w, h) = (width as usize, height as usize);
+    let mut buf = vec![0.0; w * h];
+    if w > 0 && h > 0 {
+        let band = h.div_ceil(thread_count(threads));
+        let fill = |first: usize, rows: &mut [f32]| {
+            for (i, out) in rows.chunks_mut(w).enumerate() {
+                row(first + i, out);
+            }
+        };
+        if band == h {
+            fill(0, &mut buf);
+        } else {
+            let fill = &fill;
+            thread::scope(|s| {
+                for (i, rows) in buf.chunks_mut(band * w).enumerate() {
+                    s.spawn(move || fill(i * band, rows));
+                }
+            });
+        }
+    }
+    Plane::from_raw(width, height, buf).unwrap()
+}
+
+// f applied to every item, on `threads` threads that each take the next unclaimed item so long
+// and short items even out. results are in the order of `items`
+pub fn map<T: Sync, R: Send>(items: &[T], threads: usize, f: impl Fn(&T) -> R + Sync) -> Vec<R> {
+    let threads = thread_count(threads).min(items.len());
+    if threads <= 1 {
+        return items.iter().map(f).collect();
+    }
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new((0..items.len()).map(|_| None).collect::<Vec<Option<R>>>());
+    thread::scope(|s| {
+        for _ in 0..threads {
+            s.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                if i >= items.len() {
+                    break;
+                }
+                let result = f(&items[i]);
+                results.lock().unwrap()[i] = Some(result);
+            });
+        }
+    });
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .flatten()
+        .collect()
+}
+
+// channel c as get_pixel reads it, i.e. after converting to 8 bit rgba
+pub fn channel(img: &DynamicImage, c: usize) -> Plane {
+    let (w, h) = img.dimensions();
+    let data = match img {
+        DynamicImage::ImageLuma8(buf) => buf.as_raw().iter().map(|&v| v as f32).collect(),
+        DynamicImage::ImageRgb8(buf) => buf.pixels().map(|p| p[c] as f32).collect(),
+        DynamicImage::ImageRgba8(buf) => buf.pixels().map(|p| p[c] as f32).collect(),
+        _ => img.to_rgba8().pixels().map(|p| p[c] as f32).collect(),
+    };
+    Plane::from_raw(w, h, data).unwrap()
+}
+
+// the grey values written into a copy of img, which keeps the input's colour type like the
+// per-pixel versions do
+fn like(img: &DynamicImage, grey: &Plane) -> DynamicImage {
+    let (w, h) = grey.dimensions();
+    let v = |x, y| grey.get_pixel(x, y)[0] as u8;
+    match img {
+        DynamicImage::ImageLuma8(_) => {
+            DynamicImage::ImageLuma8(GrayImage::from_fn(w, h, |x, y| Luma([v(x, y)])))
+        }
+        DynamicImage::ImageRgb8(_) => {
+            DynamicImage::ImageRgb8(RgbImage::from_fn(w, h, |x, y| Rgb([v(x, y); 3])))
+        }
+        DynamicImage::ImageRgba8(_) => {
+            DynamicImage::ImageRgba8(RgbaImage::from_fn(w, h, |x, y| {
+                let v = v(x, y);
+                Rgba([v, v, v, 255])
+            }))
+        }
+        _ => {
+            let mut out = img.clone();
+            for (x, y, p) in grey.enumerate_pixels() {
+                let v = p[0] as u8;
+                out.put_pixel(x, y, Rgba([v, v, v, 255]));
+            }
+            out
+        }
+    }
+}
+
+// out(x, y) = finish(sum of weight * plane(x + dx, y + dy) over the (dx, dy, weight) taps), with
+// coordinates clamped to the image. a whole row is accumulated one tap at a time, so every pixel
+// still adds its terms up in the order of `taps` but the inner loop runs over contiguous memory
+fn correlate<T>(
+    plane: &Plane,
+    taps: &[(i32, i32, T)],
+    threads: usize,
+    finish: impl Fn(T) -> f32 + Sync,
+) -> Plane
+where
+    T: Copy + Default + AddAssign + Mul<Output = T> + From<f32> + Send + Sync,
+{
+    let (w, h) = (plane.width() as usize, plane.height() as usize);
+    let pad = taps
+        .iter()
+        .map(|t| t.0.unsigned_abs() as usize)
+        .max()
+        .unwrap_or(0);
+    // every row converted and padded with copies of its edge pixels
+    let stride = w + 2 * pad;
+    let mut padded = Vec::with_capacity(stride * h);
+    for row in plane.as_raw().chunks_exact(w) {
+        padded.extend(std::iter::repeat_n(T::from(row[0]), pad));
+        padded.extend(row.iter().map(|&p| T::from(p)));
+        padded.extend(std::iter::repeat_n(T::from(row[w - 1]), pad));
+    }
+    map_rows(plane.width(), plane.height(), threads, |y, out| {
+        let mut acc = vec![T::default(); w];
+        for &(dx, dy, weight) in taps {
+            let sy = (y as i32 + dy).clamp(0, h as i32 - 1) as usize;
+            let start = sy * stride + (pad as i32 + dx) as usize;
+            for (a, &p) in acc.iter_mut().zip(&padded[start..start + w]) {
+                *a += p * weight;
+            }
+        }
+        for (v, a) in out.iter_mut().zip(acc) {
+            *v = finish(a);
+        }
+    })
+}
+
+// apply_kernel on one channel, including its offset of 2 for every kernel size
+pub fn convolve<const S: usize>(plane: &Plane, kernel: &[f64; S], threads: usize) -> Plane {
+    let size = S.sqrt() as i32;
+    let taps: Vec<(i32, i32, f64)> = (0..size)
+        .flat_map(|i| (0..size).map(move |j| (i - 2, j - 2, kernel[(i * size + j) as usize])))
+        .collect();
+    correlate(plane, &taps, threads, |acc| acc as u8 as f32)
+}
+
+// gx and gy of every pixel, exact as the inputs are whole numbers
+pub fn gradient(plane: &Plane, threads: usize) -> (Plane, Plane) {
+    let pass = |kernel: &[[i32; 3]; 3]| {
+        let taps: Vec<(i32, i32, f32)> = (0..3)
+            .flat_map(|i| {
+                (0..3).map(move |j| (i - 1, j - 1, kernel[j as usize][i as usize] as f32))
+            })
+            .filter(|t| t.2 != 0.0)
+            .collect();
+        correlate(plane, &taps, threads, |g| g)
+    };
+    (pass(&SOBEL_X), pass(&SOBEL_Y))
+}
+
+pub fn sobel_threshold(
+    img: &DynamicImage,
+    threshold: u8,
+    use_g: bool,
+    threads: usize,
+) -> DynamicImage {
+    let (gx, gy) = gradient(&channel(img, 0), threads);
+    let (gx, gy) = (gx.as_raw(), gy.as_raw());
+    let w = img.width() as usize;
+    let out = map_rows(img.width(), img.height(), threads, |y, out| {
+        for (x, v) in out.iter_mut().enumerate() {
+            let g = edge_magnitude((gx[y * w + x] as i32, gy[y * w + x] as i32)) as u8;
+            *v = match g >= threshold && x > 0 && y > 0 {
+                true if use_g => g as f32,
+                true => 255.0,
+                false => 0.0,
+            };
+        }
+    });
+    like(img, &out)
+}
+
+const STRONG: f32 = 255.0;
+const WEAK: f32 = 128.0;
+
+fn hysteresis(edges: &mut Plane) {
+    let (w, h) = (edges.width() as i32, edges.height() as i32);
+    let buf: &mut [f32] = edges;
+    let mut stack: Vec<usize> = (0..buf.len()).filter(|&i| buf[i] == STRONG).collect();
+    while let Some(i) = stack.pop() {
+        let (x, y) = ((i % w as usize) as i32, (i / w as usize) as i32);
+        for (dx, dy) in [
+            (-1, -1),
+            (0, -1),
+            (1, -1),
+            (-1, 0),
+            (1, 0),
+            (-1, 1),
+            (0, 1),
+            (1, 1),
+        ] {
+            let (nx, ny) = (x + dx, y + dy);
+            if nx < 0 || ny < 0 || nx >= w || ny >= h {
+                continue;
+            }
+            let n = (ny * w + nx) as usize;
+            if buf[n] == WEAK {
+                buf[n] = STRONG;
+                stack.push(n);
+            }
+        }
+    }
+    buf.iter_mut()
+        .filter(|v| **v == WEAK)
+        .for_each(|v| *v = 0.0);
+}
+
+// blur, sobel, non-maximum suppression and the double threshold are one pass each over the
+// bands, only hysteresis (a flood fill) runs on one thread
+pub fn canny(
+    img: &DynamicImage,
+    low_threshold: f32,
+    high_threshold: f32,
+    threads: usize,
+) -> Result<DynamicImage> {
+    edge_detection::check_thresholds(low_threshold, high_threshold)?;
+
+    let blurred = convolve(&channel(img, 0), &GAUSSIAN_5X5, threads);
+    let (gx, gy) = gradient(&blurred, threads);
+    let (gx, gy) = (gx.as_raw(), gy.as_raw());
+    let (w, h) = (img.width() as i32, img.height() as i32);
+    let g = |i: usize| (gx[i] as i32, gy[i] as i32);
+    // orders pixels the same as their magnitudes, without the sqrt
+    let squared = |i: usize| g(i).0 * g(i).0 + g(i).1 * g(i).1;
+    let inside = |x: i32, y: i32| x >= 0 && y >= 0 && x < w && y < h;
+
+    let mut edges = map_rows(img.width(), img.height(), threads, |y, out| {
+        let y = y as i32;
+        for (x, v) in out.iter_mut().enumerate() {
+            let x = x as i32;
+            let i = (y * w + x) as usize;
+            let mut magnitude = if x > 0 && y > 0 {
+                edge_magnitude(g(i)) as u8
+            } else {
+                0
+            };
+
+            // as suppressing only ever lowers it to 0, anything under the low threshold ends up 0
+            // anyway and can skip the atan2
+            if (magnitude as f32) < low_threshold {
+                *v = 0.0;
+                continue;
+            }
+
+            let (a, b) = pixel_dir_offsets(compass_angle(edge_direction(g(i))));
+            let (x1, y1, x2, y2) = (x + a.0, y + a.1, x + b.0, y + b.1);
+            if inside(x1, y1) && inside(x2, y2) {
+                let (n1, n2) = ((y1 * w + x1) as usize, (y2 * w + x2) as usize);
+                if squared(i) < squared(n1) || squared(i) < squared(n2) {
+                    magnitude = 0;
+                }
+            }
+
+            let magnitude = magnitude as f32;
+            *v = if magnitude >= high_threshold {
+                STRONG
+            } else if magnitude >= low_threshold {
+                WEAK
+            } else {
+                0.0
+            };
+        }
+    });
+    hysteresis(&mut edges);
+    Ok(like(img, &edges))
+}
+
+// horizontal then vertical pass per channel, rounding to 8 bit in between
+pub fn gaussian_blur_sigma(img: &DynamicImage, sigma: f32, threads: usize) -> DynamicImage {
+    if sigma <= 0.0 {
+        return img.clone();
+    }
+    let (radius, kernel) = edge_detection::gaussian_kernel(sigma);
+    let (w, h) = img.dimensions();
+    let pass = |src: &Plane, dx: i32, dy: i32| {
+        let taps: Vec<(i32, i32, f32)> = kernel
+            .iter()
+            .enumerate()
+            .map(|(k, &weight)| ((k as i32 - radius) * dx, (k as i32 - radius) * dy, weight))
+            .collect();
+        correlate(src, &taps, threads, |acc| acc.round() as u8 as f32)
+    };
+    let [r, g, b] = [0, 1, 2].map(|c| pass(&pass(&channel(img, c), 1, 0), 0, 1));
+    let rgba = (r.iter().zip(g.iter()).zip(b.iter()))
+        .flat_map(|((&r, &g), &b)| [r as u8, g as u8, b as u8, 255])
+        .collect();
+    DynamicImage::ImageRgba8(RgbaImage::from_raw(w, h, rgba).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // busy enough that every branch of the edge detectors is taken
+    fn noise() -> DynamicImage {
+        let img = RgbaImage::from_fn(61, 47, |x, y| {
+            let v = |k: u32| ((x * 37 + y * 91 + k) ^ (x * y * k)) as u8;
+            let ring = ((x as i32 - 30).pow(2) + (y as i32 - 23).pow(2)) < 200;
+            if ring {
+                Rgba([250, 240, v(3), 255])
+            } else {
+                Rgba([v(1) / 4, v(2) / 3, v(5), 200])
+            }
+        });
+        DynamicImage::ImageRgba8(img)
+    }
+
+    #[test]
+    fn test_same_as_sequential() {
+        let noise = noise();
+        let inputs = [
+            DynamicImage::ImageRgb8(noise.to_rgb8()),
+            DynamicImage::ImageLuma8(noise.to_luma8()),
+            noise,
+            crate::img_to_line::get_image("images/shapes1.png")
+                .unwrap()
+                .crop_imm(0, 0, 160, 120),
+        ];
+        for img in inputs.iter() {
+            let canny = edge_detection::canny(img, 30.0, 90.0).unwrap();
+            let sobel = edge_detection::sobel_threshold(img, 128, false);
+            let sobel_g = edge_detection::sobel(img);
+            let blurred = edge_detection::gaussian_blur_sigma(img, 1.4);
+            for threads in [1, 3] {
+                assert_eq!(canny, super::canny(img, 30.0, 90.0, threads).unwrap());
+                assert_eq!(sobel, sobel_threshold(img, 128, false, threads));
+                assert_eq!(sobel_g, sobel_threshold(img, 0, true, threads));
+                assert_eq!(blurred, gaussian_blur_sigma(img, 1.4, threads));
+            }
+        }
+    }
+
+    #[test]
+    fn test_map_keeps_order() {
+        let items: Vec<usize> = (0..100).collect();
+        assert_eq!(map(&items, 4, |i| i * i), map(&items, 1, |i| i * i));
+        assert_eq!(map(&items, 4, |i| i * 2)[99], 198);
+        assert!(map(&[] as &[usize], 4, |i| *i).is_empty());
+    }
+}
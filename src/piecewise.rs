@@ -0,0 +1,503 @@
+use crate::fourier::FourierSeries;
+use crate::img_to_line::Path;
+use crate::parallel::{self, Plane};
+use image::DynamicImage;
+use serde::{Deserialize, Serialize};
+
+// A single Fourier series rings wherever the path turns sharply, so here paths are split at
+// their corners and every smooth piece between two corners is fitted on its own.
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CornerDetector {
+    Angle, // how far the path turns over `window` points either side
+    // Harris response of the image under the path, threshold is a fraction of the strongest
+    Harris { k: f32, threshold: f32 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SegmentModel {
+    Auto, // the simplest of line, polynomial and Fourier series within the tolerance
+    Line,
+    Polynomial,
+    Fourier,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PiecewiseOptions {
+    pub detector: CornerDetector,
+    pub angle: f32,         // degrees the path has to turn through at a corner
+    pub window: usize,      // points either side of a corner the turn is measured over
+    pub min_segment: usize, // fewest points between two corners
+    pub model: SegmentModel,
+    pub tolerance: f32,   // pixels, for SegmentModel::Auto
+    pub degree: usize,    // of the polynomials
+    pub harmonics: usize, // of the Fourier pieces
+}
+
+impl Default for PiecewiseOptions {
+    fn default() -> Self {
+        PiecewiseOptions {
+            detector: CornerDetector::Angle,
+            angle: 45.0,
+            window: 4,
+            min_segment: 6,
+            model: SegmentModel::Auto,
+            tolerance: 1.5,
+            degree: 3,
+            harmonics: 8,
+        }
+    }
+}
+
+// one smooth piece, u goes from 0 at its first corner to 1 at the next
+#[derive(Debug, Clone, PartialEq)]
+pub enum Segment {
+    Line {
+        start: (f32, f32),
+        end: (f32, f32),
+    },
+    Polynomial {
+        x: Vec<f32>, // coefficients of 1, u, u^2, ...
+        y: Vec<f32>,
+    },
+    Fourier(FourierSeries), // the traced half, as FourierSeries::sample does
+}
+
+// the pieces of a path in order, segments[i] covers t from breaks[i] to breaks[i + 1] and the
+// breaks are spread by the number of points in each piece, so t runs from 0 to 1 like sample()
+#[derive(Debug, Clone, PartialEq)]
+pub struct PiecewiseCurve {
+    pub segments: Vec<Segment>,
+    pub breaks: Vec<f32>,
+}
+
+fn polynomial(coefficients: &[f32], u: f32) -> f32 {
+    coefficients.iter().rev().fold(0.0, |acc, c| acc * u + c)
+}
+
+impl Segment {
+    pub fn evaluate(&self, u: f32) -> (f32, f32) {
+        match self {
+            Segment::Line { start, end } => (
+                start.0 + (end.0 - start.0) * u,
+                start.1 + (end.1 - start.1) * u,
+            ),
+            Segment::Polynomial { x, y } => (polynomial(x, u), polynomial(y, u)),
+            Segment::Fourier(series) => series.evaluate(u),
+        }
+    }
+
+    // numbers it takes to write down
+    pub fn parameters(&self) -> usize {
+        match self {
+            Segment::Line { .. } => 4,
+            Segment::Polynomial { x, y } => x.len() + y.len(),
+            Segment::Fourier(series) => series.parameters(),
+        }
+    }
+
+    // Desmos expression in `var`, y negated like FourierSeries::to_equation
+    pub fn to_equation_in(&self, var: &str) -> String {
+        let poly = |c: &[f32]| {
+            let terms: Vec<String> = c
+                .iter()
+                .enumerate()
+                .map(|(p, c)| match p {
+                    0 => format!("{c}"),
+                    1 => format!("{c}{var}"),
+                    _ => format!("{c}{var}^{p}"),
+                })
+                .collect();
+            terms.join("+")
+        };
+        match self {
+            Segment::Line { start, end } => format!(
+                "(({}+{}{var}),-({}+{}{var}))",
+                start.0,
+                end.0 - start.0,
+                start.1,
+                end.1 - start.1
+            ),
+            Segment::Polynomial { x, y } => format!("(({}),-({}))", poly(x), poly(y)),
+            Segment::Fourier(series) => series.to_equation_in(var),
+        }
+    }
+}
+
+impl PiecewiseCurve {
+    pub fn evaluate(&self, t: f32) -> (f32, f32) {
+        let i = self.breaks[1..self.breaks.len() - 1].partition_point(|&b| b <= t);
+        let (a, b) = (self.breaks[i], self.breaks[i + 1]);
+        let u = if b > a { (t - a) / (b - a) } else { 0.0 };
+        self.segments[i].evaluate(u.clamp(0.0, 1.0))
+    }
+
+    pub fn sample(&self, n: usize) -> Vec<(f32, f32)> {
+        let steps = n.max(2) - 1;
+        (0..=steps)
+            .map(|i| self.evaluate(i as f32 / steps as f32))
+            .collect()
+    }
+
+    pub fn parameters(&self) -> usize {
+        self.segments.iter().map(|s| s.parameters()).sum()
+    }
+
+    // one Desmos expression per piece, each restricted to its share of t in [0, 1]
+    pub fn to_equations(&self) -> Vec<String> {
+        self.segments
+            .iter()
+            .zip(self.breaks.windows(2))
+            .map(|(segment, t)| {
+                let var = format!("((t-{})/{})", t[0], t[1] - t[0]);
+                format!("{}{{{}<=t<={}}}", segment.to_equation_in(&var), t[0], t[1])
+            })
+            .collect()
+    }
+}
+
+// how far the path turns at each point, in degrees, between the directions to the points
+// `window` before and after it
+fn turning_angles(path: &[(f32, f32)], window: usize) -> Vec<f32> {
+    let n = path.len();
+    (0..n)
+        .map(|i| {
+            if i < window || i + window >= n {
+                return 0.0;
+            }
+            let (p, a, b) = (path[i], path[i - window], path[i + window]);
+            let (ux, uy) = (p.0 - a.0, p.1 - a.1);
+            let (vx, vy) = (b.0 - p.0, b.1 - p.1);
+            (ux * vy - uy * vx)
+                .atan2(ux * vx + uy * vy)
+                .abs()
+                .to_degrees()
+        })
+        .collect()
+}
+
+// Harris corner response R = det(M) - k trace(M)^2, M summing the gradient products over a 5x5
+// window
+pub fn harris_response(img: &DynamicImage, k: f32, threads: usize) -> Plane {
+    let (gx, gy) = parallel::gradient(&parallel::channel(img, 0), threads);
+    let (w, h) = gx.dimensions();
+    parallel::map_rows(w, h, threads, |y, out| {
+        for (x, r) in out.iter_mut().enumerate() {
+            let (mut xx, mut yy, mut xy) = (0.0, 0.0, 0.0);
+            for sy in y.saturating_sub(2)..(y + 3).min(h as usize) {
+                for sx in x.saturating_sub(2)..(x + 3).min(w as usize) {
+                    let (dx, dy) = (
+                        gx.as_raw()[sy * w as usize + sx],
+                        gy.as_raw()[sy * w as usize + sx],
+                    );
+                    xx += dx * dx;
+                    yy += dy * dy;
+                    xy += dx * dy;
+                }
+            }
+            *r = xx * yy - xy * xy - k * (xx + yy).powi(2);
+        }
+    })
+}
+
+// indices of the strongest points at or above threshold, at least min_gap apart and from the ends
+fn strongest(strength: &[f32], threshold: f32, min_gap: usize) -> Vec<usize> {
+    let n = strength.len();
+    let mut candidates: Vec<usize> = (min_gap..n.saturating_sub(min_gap))
+        .filter(|&i| strength[i] >= threshold && strength[i] > 0.0)
+        .collect();
+    candidates.sort_by(|&a, &b| strength[b].total_cmp(&strength[a]).then(a.cmp(&b)));
+    let mut corners: Vec<usize> = vec![];
+    for i in candidates {
+        if corners.iter().all(|&c| c.abs_diff(i) >= min_gap) {
+            corners.push(i);
+        }
+    }
+    corners.sort();
+    corners
+}
+
+// indices where the path should be split, not counting its two ends. `response` is the Harris
+// response of the image the path was traced from, only used with CornerDetector::Harris
+pub fn find_corners(
+    path: &Path,
+    response: Option<&Plane>,
+    options: &PiecewiseOptions,
+) -> Vec<usize> {
+    let min_gap = options.min_segment.max(1);
+    match (options.detector, response) {
+        (CornerDetector::Harris { threshold, .. }, Some(response)) => {
+            let strongest_response = response.as_raw().iter().copied().fold(0.0, f32::max);
+            let strength: Vec<f32> = path
+                .iter()
+                .map(|&(x, y)| {
+                    let (x, y) = (x as u32, y as u32);
+                    if x < response.width() && y < response.height() {
+                        response.get_pixel(x, y)[0]
+                    } else {
+                        0.0
+                    }
+                })
+                .collect();
+            strongest(&strength, threshold * strongest_response, min_gap)
+        }
+        _ => {
+            let points: Vec<(f32, f32)> = path.iter().map(|&(x, y)| (x as f32, y as f32)).collect();
+            let angles = turning_angles(&points, options.window.max(1));
+            strongest(&angles, options.angle, min_gap)
+        }
+    }
+}
+
+// u for each point, by distance along the piece
+pub(crate) fn chord_lengths(points: &[(f32, f32)]) -> Vec<f32> {
+    let mut u = vec![0.0];
+    for w in points.windows(2) {
+        let last = *u.last().unwrap();
+        u.push(last + (w[1].0 - w[0].0).hypot(w[1].1 - w[0].1));
+    }
+    let total = *u.last().unwrap();
+    if total > 0.0 {
+        u.iter_mut().for_each(|v| *v /= total);
+    }
+    u
+}
+
+fn max_error(segment: &Segment, points: &[(f32, f32)], u: &[f32]) -> f32 {
+    points
+        .iter()
+        .zip(u)
+        .map(|(p, &u)| {
+            let q = segment.evaluate(u);
+            (p.0 - q.0).hypot(p.1 - q.1)
+        })
+        .fold(0.0, f32::max)
+}
+
+// solves a x = b by gaussian elimination, None when a is singular
+fn solve(mut a: Vec<Vec<f64>>, mut b: Vec<f64>) -> Option<Vec<f64>> {
+    let n = b.len();
+    for col in 0..n {
+        let pivot = (col..n).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+        if a[pivot][col].abs() < 1e-12 {
+            return None;
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+        let (above, below) = a.split_at_mut(col + 1);
+        let pivot_row = &above[col];
+        for (row, r) in below.iter_mut().enumerate() {
+            let f = r[col] / pivot_row[col];
+            for (v, p) in r[col..].iter_mut().zip(&pivot_row[col..]) {
+                *v -= f * p;
+            }
+            b[col + 1 + row] -= f * b[col];
+        }
+    }
+    let mut x = vec![0.0; n];
+    for row in (0..n).rev() {
+        let sum: f64 = (row + 1..n).map(|c| a[row][c] * x[c]).sum();
+        x[row] = (b[row] - sum) / a[row][row];
+    }
+    Some(x)
+}
+
+// least squares polynomial through both end points: the straight line between them plus
+// u (1 - u) q(u), with q of degree - 2
+fn fit_polynomial(points: &[(f32, f32)], u: &[f32], degree: usize) -> Option<Segment> {
+    let (first, last) = (points[0], points[points.len() - 1]);
+    let free = degree.checked_sub(1)?;
+    if free == 0 || points.len() < degree + 1 {
+        return None;
+    }
+    let basis = |u: f32, j: usize| (u * (1.0 - u) * u.powi(j as i32)) as f64;
+    let mut a = vec![vec![0.0; free]; free];
+    let (mut bx, mut by) = (vec![0.0; free], vec![0.0; free]);
+    for (p, &u) in points.iter().zip(u) {
+        let rx = (p.0 - (first.0 + (last.0 - first.0) * u)) as f64;
+        let ry = (p.1 - (first.1 + (last.1 - first.1) * u)) as f64;
+        for i in 0..free {
+            for (j, v) in a[i].iter_mut().enumerate() {
+                *v += basis(u, i) * basis(u, j);
+            }
+            bx[i] += basis(u, i) * rx;
+            by[i] += basis(u, i) * ry;
+        }
+    }
+    let qx = solve(a.clone(), bx)?;
+    let qy = solve(a, by)?;
+    // u (1 - u) u^j = u^(j + 1) - u^(j + 2)
+    let monomials = |start: f32, end: f32, q: &[f64]| {
+        let mut c = vec![0.0; degree + 1];
+        c[0] = start;
+        c[1] = end - start;
+        for (j, &q) in q.iter().enumerate() {
+            c[j + 1] += q as f32;
+            c[j + 2] -= q as f32;
+        }
+        c
+    };
+    Some(Segment::Polynomial {
+        x: monomials(first.0, last.0, &qx),
+        y: monomials(first.1, last.1, &qy),
+    })
+}
+
+pub fn fit_segment(points: &[(f32, f32)], options: &PiecewiseOptions) -> Segment {
+    let line = Segment::Line {
+        start: points[0],
+        end: points[points.len() - 1],
+    };
+    let fourier = || Segment::Fourier(FourierSeries::fit_points(points, options.harmonics));
+    let u = chord_lengths(points);
+    match options.model {
+        SegmentModel::Line => line,
+        SegmentModel::Polynomial => fit_polynomial(points, &u, options.degree).unwrap_or(line),
+        SegmentModel::Fourier => fourier(),
+        SegmentModel::Auto => {
+            if max_error(&line, points, &u) <= options.tolerance {
+                return line;
+            }
+            match fit_polynomial(points, &u, options.degree) {
+                Some(poly) if max_error(&poly, points, &u) <= options.tolerance => poly,
+                _ => fourier(),
+            }
+        }
+    }
+}
+
+pub fn fit(path: &Path, response: Option<&Plane>, options: &PiecewiseOptions) -> PiecewiseCurve {
+    let points: Vec<(f32, f32)> = path.iter().map(|&(x, y)| (x as f32, y as f32)).collect();
+    if points.len() < 2 {
+        let p = points.first().copied().unwrap_or_default();
+        return PiecewiseCurve {
+            segments: vec![Segment::Line { start: p, end: p }],
+            breaks: vec![0.0, 1.0],
+        };
+    }
+    let mut cuts = vec![0];
+    cuts.extend(find_corners(path, response, options));
+    cuts.push(points.len() - 1);
+
+    let last = (points.len() - 1) as f32;
+    let segments = cuts
+        .windows(2)
+        .map(|c| fit_segment(&points[c[0]..=c[1]], options))
+        .collect();
+    let breaks = cuts.iter().map(|&c| c as f32 / last).collect();
+    PiecewiseCurve { segments, breaks }
+}
+
+// a square traced clockwise from its top left corner, for tests here and in other modules
+#[cfg(test)]
+pub(crate) fn square(side: i32) -> Path {
+    let mut path = vec![];
+    path.extend((0..side).map(|i| (10 + i, 10)));
+    path.extend((0..side).map(|i| (10 + side, 10 + i)));
+    path.extend((0..side).map(|i| (10 + side - i, 10 + side)));
+    path.extend((0..=side).map(|i| (10, 10 + side - i)));
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_square_corners() {
+        let path = square(30);
+        let options = PiecewiseOptions::default();
+        assert_eq!(find_corners(&path, None, &options), vec![30, 60, 90]);
+
+        let curve = fit(&path, None, &options);
+        assert_eq!(curve.segments.len(), 4);
+        assert!(curve
+            .segments
+            .iter()
+            .all(|s| matches!(s, Segment::Line { .. })));
+        // no ringing: every point is on the square
+        for (x, y) in curve.sample(400) {
+            let on_side = [x - 10.0, x - 40.0, y - 10.0, y - 40.0]
+                .iter()
+                .any(|d| d.abs() < 1e-3);
+            assert!(on_side, "({x}, {y})");
+        }
+        assert_eq!(curve.evaluate(0.25), (40.0, 10.0));
+
+        let global = FourierSeries::fit_with(&path, 10);
+        let wobble = global
+            .sample(400)
+            .iter()
+            .map(|&(x, y)| {
+                [x - 10.0, x - 40.0, y - 10.0, y - 40.0]
+                    .iter()
+                    .map(|d| d.abs())
+                    .fold(f32::INFINITY, f32::min)
+            })
+            .fold(0.0, f32::max);
+        assert!(wobble > 0.5, "{wobble}");
+    }
+
+    #[test]
+    fn test_segment_models() {
+        let arc: Vec<(f32, f32)> = (0..=40)
+            .map(|i| {
+                let a = (i as f32 / 40.0) * std::f32::consts::FRAC_PI_2;
+                (50.0 * a.cos(), 50.0 * a.sin())
+            })
+            .collect();
+        let options = PiecewiseOptions::default();
+        let segment = fit_segment(&arc, &options);
+        assert!(matches!(segment, Segment::Polynomial { .. }));
+        // polynomials go through both end points, so the pieces join up
+        let (start, end) = (segment.evaluate(0.0), segment.evaluate(1.0));
+        assert!((start.0 - 50.0).abs() < 1e-3 && start.1.abs() < 1e-3);
+        assert!(end.0.abs() < 1e-3 && (end.1 - 50.0).abs() < 1e-3);
+
+        let wiggle: Vec<(f32, f32)> = (0..=80)
+            .map(|i| (i as f32, 10.0 * (i as f32 / 5.0).sin()))
+            .collect();
+        assert!(matches!(
+            fit_segment(&wiggle, &options),
+            Segment::Fourier(_)
+        ));
+    }
+
+    #[test]
+    fn test_equations_have_domains() {
+        let curve = fit(&square(30), None, &PiecewiseOptions::default());
+        let equations = curve.to_equations();
+        assert_eq!(equations.len(), 4);
+        assert!(equations[0].ends_with("{0<=t<=0.25}"));
+        assert!(equations[3].ends_with("{0.75<=t<=1}"));
+        assert!(equations[1].contains("((t-0.25)/0.25)"));
+    }
+
+    #[test]
+    fn test_harris_corners() {
+        let mut img = image::GrayImage::new(60, 60);
+        for y in 15..45 {
+            for x in 15..45 {
+                img.put_pixel(x, y, image::Luma([255]));
+            }
+        }
+        let img = DynamicImage::ImageLuma8(img);
+        let response = harris_response(&img, 0.04, 1);
+        let path: Path = square(30).iter().map(|&(x, y)| (x + 5, y + 5)).collect();
+        let options = PiecewiseOptions {
+            detector: CornerDetector::Harris {
+                k: 0.04,
+                threshold: 0.1,
+            },
+            ..Default::default()
+        };
+        let corners = find_corners(&path, Some(&response), &options);
+        assert_eq!(corners.len(), 3);
+        for (c, expected) in corners.iter().zip([30, 60, 90]) {
+            assert!(c.abs_diff(expected) <= 2, "{corners:?}");
+        }
+    }
+}
@@ -0,0 +1,715 @@
+use crate::bezier::{self, BezierCurve, BezierOptions};
+use crate::budget::{self, BudgetOptions};
+use crate::error::{Error, Result};
+use crate::filter::{self, Criterion, Ranking};
+use crate::fourier::{self, FourierSeries, Window};
+use crate::img_to_line::{self, Path};
+use crate::metrics::{self, Metrics};
+use crate::model::{self, CurveModel, Model, ModelKind, SelectOptions};
+use crate::piecewise::{self, CornerDetector, PiecewiseCurve, PiecewiseOptions};
+use crate::spline::{self, Spline, SplineOptions};
+use crate::{audio, config, dxf, epicycles, parallel, plotter, render, svg};
+use image::{DynamicImage, GenericImageView, Rgba};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// tracing is a recursive dfs, so it runs on a thread with a big stack
+const TRACE_STACK_SIZE: usize = 32 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Stage {
+    Edges, // preprocessing and edge detection
+    Trace,
+    Filter,
+    Fit,
+    Export,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PreprocessOptions {
+    pub blur_sigma: f32, // 0 for no blur
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EdgeDetector {
+    Sobel { threshold: u8 },
+    Canny { low: f32, high: f32 },
+    None, // the input is already line art
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EdgeOptions {
+    pub detector: EdgeDetector,
+}
+
+impl Default for EdgeOptions {
+    fn default() -> Self {
+        EdgeOptions {
+            detector: EdgeDetector::Sobel { threshold: 128 },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TraceOptions {
+    #[serde(with = "crate::config::hex_colour")]
+    pub colour: Rgba<u8>, // colour of the traced pixels
+    pub min_length: usize,
+}
+
+impl Default for TraceOptions {
+    fn default() -> Self {
+        TraceOptions {
+            colour: img_to_line::WHITE,
+            min_length: img_to_line::MIN_PATH_LEN,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FilterOptions {
+    pub max_curves: usize, // keep the first n paths of the ranking, 0 for all, ignored with fit.budget
+    pub criteria: Vec<Criterion>, // paths have to meet every one of them
+    pub ranking: Ranking,
+    pub mask: Option<PathBuf>, // region of interest, white inside, for Criterion::Inside and saliency
+}
+
+impl Default for FilterOptions {
+    fn default() -> Self {
+        FilterOptions {
+            max_curves: 32,
+            criteria: vec![],
+            ranking: Ranking::Longest,
+            mask: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FitOptions {
+    pub max_harmonics: usize,
+    pub piecewise: Option<PiecewiseOptions>, // also fit the pieces between corners, for Format::Piecewise
+    pub window: Window, // tapers the harmonics of every curve when it is evaluated or exported
+    pub bezier: Option<BezierOptions>, // also fit cubic Bézier segments, for Format::Bezier and svg
+    pub spline: Option<SplineOptions>, // also fit splines, for Format::Spline and Format::SplineTex
+    pub select: Option<SelectOptions>, // pick a model per path, otherwise every path is a series
+    pub budget: Option<BudgetOptions>, // share terms or characters between curves, max_harmonics each at most
+}
+
+impl Default for FitOptions {
+    fn default() -> Self {
+        FitOptions {
+            max_harmonics: fourier::MAX_FREQS,
+            piecewise: None,
+            window: Window::None,
+            bezier: None,
+            spline: None,
+            select: None,
+            budget: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Format {
+    Edges,     // edges.png, and blurred.png when blurring
+    Lines,     // traced paths in random colours, lines.png
+    Txt,       // Desmos equations, equations.txt
+    Svg,       // lines.svg
+    Png,       // curves plotted over the faded input, equations.png
+    Gif,       // epicycles of the longest curve, epicycles.gif
+    Frames,    // the same as numbered pngs, epicycles_0000.png...
+    Wav,       // oscilloscope audio, lines.wav
+    Gcode,     // lines.gcode
+    Hpgl,      // lines.hpgl
+    Dxf,       // lines.dxf
+    Metrics,   // metrics.json
+    Config,    // the effective configuration, config.toml
+    Piecewise, // pieces split at corners as Desmos equations with t domains, piecewise.txt
+    Bezier,    // cubic Bézier segments as Desmos equations, bezier.txt
+    Spline,    // spline segments as Desmos equations, spline.txt
+    SplineTex, // the same as LaTeX cases, spline.tex
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExportOptions {
+    pub dir: PathBuf,
+    pub formats: Vec<Format>,
+    pub tolerance: f32, // for metrics
+    pub plot_samples: usize,
+    pub svg: svg::SvgOptions,
+    pub render: render::RenderOptions,
+    pub epicycles: epicycles::EpicycleOptions,
+    pub audio: audio::AudioOptions,
+    pub plot: plotter::PlotOptions,
+    pub dxf: dxf::DxfOptions,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        ExportOptions {
+            dir: PathBuf::from("generated"),
+            formats: vec![Format::Txt],
+            tolerance: 2.0,
+            plot_samples: 512,
+            svg: Default::default(),
+            render: Default::default(),
+            epicycles: Default::default(),
+            audio: Default::default(),
+            plot: Default::default(),
+            dxf: Default::default(),
+        }
+    }
+}
+
+// every intermediate artifact, stages that did not run leave their fields empty
+pub struct PipelineResult {
+    pub image: DynamicImage,
+    pub preprocessed: DynamicImage,
+    pub edges: DynamicImage,
+    pub paths: Vec<Path>,    // everything that was traced
+    pub selected: Vec<Path>, // paths left after filtering, curves[i] is fitted to selected[i]
+    pub curves: Vec<FourierSeries>,
+    pub models: Vec<Model>, // what the exporters write, the series themselves unless fit.select
+    pub piecewise: Vec<PiecewiseCurve>, // only with fit.piecewise set, one per selected path
+    pub beziers: Vec<BezierCurve>, // only with fit.bezier set, one per selected path
+    pub splines: Vec<Spline>, // only with fit.spline set, one per selected path
+    pub metrics: Option<Metrics>,
+    pub written: Vec<PathBuf>,
+    pub timings: Vec<(Stage, Duration)>,
+}
+
+// preprocessing -> edge detection -> tracing -> path filtering -> fitting -> export
+//
+//     let result = Pipeline::new()
+//         .preprocess(PreprocessOptions { blur_sigma: 1.4 })
+//         .edges(EdgeOptions { detector: EdgeDetector::Canny { low: 50.0, high: 100.0 } })
+//         .export(ExportOptions::default())
+//         .run_file("images/toronto.png")?;
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Pipeline {
+    pub preprocess: PreprocessOptions,
+    pub edges: EdgeOptions,
+    pub trace: TraceOptions,
+    pub filter: FilterOptions,
+    pub fit: FitOptions,
+    pub export: Option<ExportOptions>, // nothing is written without it
+    pub threads: usize,                // for the image kernels and fitting, 0 for one per core
+}
+
+impl Pipeline {
+    pub fn new() -> Pipeline {
+        Pipeline::default()
+    }
+
+    pub fn preprocess(mut self, options: PreprocessOptions) -> Pipeline {
+        self.preprocess = options;
+        self
+    }
+
+    pub fn edges(mut self, options: EdgeOptions) -> Pipeline {
+        self.edges = options;
+        self
+    }
+
+    pub fn trace(mut self, options: TraceOptions) -> Pipeline {
+        self.trace = options;
+        self
+    }
+
+    pub fn filter(mut self, options: FilterOptions) -> Pipeline {
+        self.filter = options;
+        self
+    }
+
+    pub fn fit(mut self, options: FitOptions) -> Pipeline {
+        self.fit = options;
+        self
+    }
+
+    pub fn export(mut self, options: ExportOptions) -> Pipeline {
+        self.export = Some(options);
+        self
+    }
+
+    pub fn detect_edges(&self, img: &DynamicImage) -> Result<(DynamicImage, DynamicImage)> {
+        if img.width() == 0 || img.height() == 0 {
+            return Err(Error::EmptyInput("the image has no pixels".to_string()));
+        }
+        let sigma = self.preprocess.blur_sigma;
+        if sigma.is_nan() || sigma < 0.0 {
+            return Err(Error::InvalidParameter(format!(
+                "blur sigma {sigma} must not be negative"
+            )));
+        }
+        let preprocessed = parallel::gaussian_blur_sigma(img, sigma, self.threads);
+        let edges = match self.edges.detector {
+            EdgeDetector::Sobel { threshold } => {
+                parallel::sobel_threshold(&preprocessed, threshold, false, self.threads)
+            }
+            EdgeDetector::Canny { low, high } => {
+                parallel::canny(&preprocessed, low, high, self.threads)?
+            }
+            EdgeDetector::None => preprocessed.clone(),
+        };
+        Ok((preprocessed, edges))
+    }
+
+    pub fn trace_paths(&self, edges: &DynamicImage) -> Result<Vec<Path>> {
+        const THREAD: &str = "tracing thread";
+        thread::scope(|s| {
+            let tracer = thread::Builder::new()
+                .name(THREAD.to_string())
+                .stack_size(TRACE_STACK_SIZE)
+                .spawn_scoped(s, || {
+                    img_to_line::edges_to_lines(edges, self.trace.colour, self.trace.min_length)
+                })
+                .map_err(Error::io(THREAD))?;
+            tracer.join().map_err(|panic| {
+                let message = match panic.downcast_ref::<&str>() {
+                    Some(message) => message.to_string(),
+                    None => panic.downcast_ref::<String>().cloned().unwrap_or_default(),
+                };
+                let e = std::io::Error::other(format!("panicked while tracing: {message}"));
+                Error::Io(PathBuf::from(THREAD), e)
+            })
+        })
+    }
+
+    // `img` is the preprocessed image the paths were traced from
+    pub fn filter_paths(&self, paths: &[Path], img: &DynamicImage) -> Result<Vec<Path>> {
+        let options = &self.filter;
+        let strength = options.ranking.needs_strength()
+            || options
+                .criteria
+                .iter()
+                .any(|c| matches!(c, Criterion::Strength { .. }));
+        let context = filter::Context::new(img, strength, options.mask.as_deref(), self.threads)?;
+        // a budget decides for itself which curves are worth drawing
+        let max_curves = match self.fit.budget {
+            Some(_) => 0,
+            None => options.max_curves,
+        };
+        Ok(filter::select(
+            paths,
+            &context,
+            &options.criteria,
+            &options.ranking,
+            max_curves,
+            self.threads,
+        ))
+    }
+
+    pub fn fit_curves(&self, paths: &[Path]) -> Vec<FourierSeries> {
+        parallel::map(paths, self.threads, |p| CurveModel::fit(p, &self.fit))
+    }
+
+    // cuts every curve down to its share of the budget, leaving out the curves and paths that get
+    // nothing
+    pub fn spend_budget(
+        &self,
+        paths: &[Path],
+        curves: &[FourierSeries],
+        options: &BudgetOptions,
+    ) -> (Vec<Path>, Vec<FourierSeries>) {
+        let harmonics = budget::allocate(paths, curves, options);
+        paths
+            .iter()
+            .zip(curves)
+            .zip(harmonics)
+            .filter(|(_, h)| *h > 0)
+            .map(|((p, c), h)| (p.clone(), c.truncated(h)))
+            .unzip()
+    }
+
+    // every path split at its corners, `img` is only needed for CornerDetector::Harris
+    pub fn fit_piecewise(
+        &self,
+        paths: &[Path],
+        img: &DynamicImage,
+        options: &PiecewiseOptions,
+    ) -> Vec<PiecewiseCurve> {
+        let response = match options.detector {
+            CornerDetector::Harris { k, .. } => {
+                Some(piecewise::harris_response(img, k, self.threads))
+            }
+            CornerDetector::Angle => None,
+        };
+        parallel::map(paths, self.threads, |p| {
+            piecewise::fit(p, response.as_ref(), options)
+        })
+    }
+
+    pub fn fit_beziers(&self, paths: &[Path], options: &BezierOptions) -> Vec<BezierCurve> {
+        parallel::map(paths, self.threads, |p| bezier::fit(p, options))
+    }
+
+    pub fn fit_splines(&self, paths: &[Path], options: &SplineOptions) -> Vec<Spline> {
+        parallel::map(paths, self.threads, |p| spline::fit(p, options))
+    }
+
+    // the cheapest model of fit.select for every path, reusing the curves that were fitted anyway
+    pub fn fit_models(&self, result: &PipelineResult) -> Vec<Model> {
+        let Some(select) = &self.fit.select else {
+            return result.curves.iter().cloned().map(Model::Fourier).collect();
+        };
+        let indices: Vec<usize> = (0..result.selected.len()).collect();
+        parallel::map(&indices, self.threads, |&i| {
+            let path = &result.selected[i];
+            let fitted = |kind| match kind {
+                ModelKind::Fourier => result.curves.get(i).cloned().map(Model::Fourier),
+                ModelKind::Piecewise => result.piecewise.get(i).cloned().map(Model::Piecewise),
+                ModelKind::Bezier => result.beziers.get(i).cloned().map(Model::Bezier),
+                ModelKind::Spline => result.splines.get(i).cloned().map(Model::Spline),
+            };
+            let candidates = select
+                .candidates
+                .iter()
+                .map(|&kind| fitted(kind).unwrap_or_else(|| Model::fit_kind(kind, path, &self.fit)))
+                .collect();
+            model::best(path, candidates, select.lambda)
+                .unwrap_or_else(|| Model::Fourier(result.curves[i].clone()))
+        })
+    }
+
+    pub fn run_file(&self, path: &str) -> Result<PipelineResult> {
+        self.run(img_to_line::get_image(path)?)
+    }
+
+    pub fn run(&self, img: DynamicImage) -> Result<PipelineResult> {
+        self.run_until(img, Stage::Export)
+    }
+
+    // runs every stage up to and including `last`
+    pub fn run_until(&self, img: DynamicImage, last: Stage) -> Result<PipelineResult> {
+        let mut timings = vec![];
+
+        let now = Instant::now();
+        let (preprocessed, edges) = self.detect_edges(&img)?;
+        timings.push((Stage::Edges, now.elapsed()));
+
+        let mut result = PipelineResult {
+            image: img,
+            preprocessed,
+            edges,
+            paths: vec![],
+            selected: vec![],
+            curves: vec![],
+            models: vec![],
+            piecewise: vec![],
+            beziers: vec![],
+            splines: vec![],
+            metrics: None,
+            written: vec![],
+            timings,
+        };
+
+        let stages = [Stage::Trace, Stage::Filter, Stage::Fit, Stage::Export];
+        for stage in stages.into_iter().filter(|s| *s <= last) {
+            let now = Instant::now();
+            match stage {
+                Stage::Trace => result.paths = self.trace_paths(&result.edges)?,
+                Stage::Filter => {
+                    result.selected = self.filter_paths(&result.paths, &result.preprocessed)?
+                }
+                Stage::Fit => {
+                    result.curves = self.fit_curves(&result.selected);
+                    if let Some(options) = &self.fit.budget {
+                        (result.selected, result.curves) =
+                            self.spend_budget(&result.selected, &result.curves, options);
+                    }
+                    if let Some(options) = &self.fit.piecewise {
+                        result.piecewise =
+                            self.fit_piecewise(&result.selected, &result.preprocessed, options);
+                    }
+                    if let Some(options) = &self.fit.bezier {
+                        result.beziers = self.fit_beziers(&result.selected, options);
+                    }
+                    if let Some(options) = &self.fit.spline {
+                        result.splines = self.fit_splines(&result.selected, options);
+                    }
+                    result.models = self.fit_models(&result);
+                }
+                Stage::Export => match &self.export {
+                    Some(options) => self.write(&mut result, options)?,
+                    None => continue,
+                },
+                Stage::Edges => unreachable!(),
+            }
+            result.timings.push((stage, now.elapsed()));
+        }
+        Ok(result)
+    }
+
+    // writes the given formats from whatever the result holds
+    pub fn write(&self, result: &mut PipelineResult, options: &ExportOptions) -> Result<()> {
+        let out = &options.dir;
+        std::fs::create_dir_all(out).map_err(Error::io(out))?;
+        let dims = result.image.dimensions();
+        let (paths, curves) = (&result.selected, &result.models);
+
+        for format in options.formats.iter() {
+            let file = match format {
+                Format::Edges => {
+                    if self.preprocess.blur_sigma > 0.0 {
+                        let file = out.join("blurred.png");
+                        result
+                            .preprocessed
+                            .save(&file)
+                            .map_err(Error::image(&file))?;
+                        result.written.push(file);
+                    }
+                    let file = out.join("edges.png");
+                    result.edges.save(&file).map_err(Error::image(&file))?;
+                    file
+                }
+                Format::Lines => {
+                    let file = out.join("lines.png");
+                    img_to_line::lines_to_img(paths, &file)?;
+                    file
+                }
+                Format::Txt => {
+                    let file = out.join("equations.txt");
+                    let mut f = File::create(&file).map_err(Error::io(&file))?;
+                    for equation in curves.iter().flat_map(|c| c.to_equations()) {
+                        writeln!(f, "{equation}").map_err(Error::io(&file))?;
+                    }
+                    file
+                }
+                Format::Svg => {
+                    let file = out.join("lines.svg");
+                    // with models selected the curves layer already has the paths that went to
+                    // Bézier segments, a layer of every Bézier fit would draw those twice
+                    let beziers = match self.fit.select {
+                        Some(_) => &[],
+                        None => &result.beziers[..],
+                    };
+                    svg::save_svg(&file, dims, paths, curves, beziers, &options.svg)?;
+                    file
+                }
+                Format::Png => {
+                    let file = out.join("equations.png");
+                    render::save_render(&file, dims, curves, Some(&result.image), &options.render)?;
+                    file
+                }
+                Format::Gif | Format::Frames => {
+                    // the ranking need not put the longest path first
+                    let longest = result
+                        .selected
+                        .iter()
+                        .zip(&result.curves)
+                        .min_by_key(|(path, _)| std::cmp::Reverse(path.len()));
+                    let Some((_, longest)) = longest else {
+                        continue;
+                    };
+                    let frames = epicycles::render_frames(longest, &options.epicycles);
+                    if *format == Format::Gif {
+                        let file = out.join("epicycles.gif");
+                        let delay = options.epicycles.frame_delay_ms;
+                        epicycles::save_gif(&file, frames, delay)?;
+                        file
+                    } else {
+                        let file = out.join("epicycles");
+                        epicycles::save_png_sequence(&file, &frames)?;
+                        file
+                    }
+                }
+                Format::Wav => {
+                    let file = out.join("lines.wav");
+                    audio::save_wav(&file, curves, &options.audio)?;
+                    file
+                }
+                Format::Gcode => {
+                    let file = out.join("lines.gcode");
+                    let polylines = plotter::curves_to_polylines(curves, options.plot_samples);
+                    plotter::save_gcode(&file, &polylines, dims, &options.plot)?;
+                    file
+                }
+                Format::Hpgl => {
+                    let file = out.join("lines.hpgl");
+                    let polylines = plotter::curves_to_polylines(curves, options.plot_samples);
+                    plotter::save_hpgl(&file, &polylines, dims, &options.plot)?;
+                    file
+                }
+                Format::Dxf => {
+                    let file = out.join("lines.dxf");
+                    dxf::save_dxf(&file, dims, paths, curves, &options.dxf)?;
+                    file
+                }
+                Format::Metrics => {
+                    let metrics = metrics::evaluate(
+                        &result.edges,
+                        self.trace.colour,
+                        paths,
+                        curves,
+                        options.tolerance,
+                    );
+                    let file = out.join("metrics.json");
+                    std::fs::write(&file, metrics.to_json()).map_err(Error::io(&file))?;
+                    result.metrics = Some(metrics);
+                    file
+                }
+                Format::Piecewise => {
+                    let file = out.join("piecewise.txt");
+                    let mut f = File::create(&file).map_err(Error::io(&file))?;
+                    for curve in result.piecewise.iter() {
+                        for equation in curve.to_equations() {
+                            writeln!(f, "{equation}").map_err(Error::io(&file))?;
+                        }
+                    }
+                    file
+                }
+                Format::Bezier => {
+                    let file = out.join("bezier.txt");
+                    let mut f = File::create(&file).map_err(Error::io(&file))?;
+                    for curve in result.beziers.iter() {
+                        for equation in curve.to_equations() {
+                            writeln!(f, "{equation}").map_err(Error::io(&file))?;
+                        }
+                    }
+                    file
+                }
+                Format::Spline => {
+                    let file = out.join("spline.txt");
+                    let mut f = File::create(&file).map_err(Error::io(&file))?;
+                    for curve in result.splines.iter() {
+                        for equation in curve.to_equations() {
+                            writeln!(f, "{equation}").map_err(Error::io(&file))?;
+                        }
+                    }
+                    file
+                }
+                Format::SplineTex => {
+                    let file = out.join("spline.tex");
+                    let tex: Vec<String> = result.splines.iter().map(|s| s.to_latex()).collect();
+                    std::fs::write(&file, tex.join("\n")).map_err(Error::io(&file))?;
+                    file
+                }
+                Format::Config => {
+                    let file = out.join("config.toml");
+                    config::save(&file, self, options)?;
+                    file
+                }
+            };
+            result.written.push(file);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square() -> DynamicImage {
+        let mut img = DynamicImage::new_rgb8(40, 40);
+        let buf = img.as_mut_rgb8().unwrap();
+        for i in 10..30 {
+            for (x, y) in [(i, 10), (i, 29), (10, i), (29, i)] {
+                buf.put_pixel(x, y, image::Rgb([255, 255, 255]));
+            }
+        }
+        img
+    }
+
+    #[test]
+    fn test_run_until() {
+        let pipeline = Pipeline::new().edges(EdgeOptions {
+            detector: EdgeDetector::None,
+        });
+        let result = pipeline.run_until(square(), Stage::Trace).unwrap();
+        assert_eq!(result.paths.len(), 1);
+        assert!(result.selected.is_empty() && result.curves.is_empty());
+
+        let result = pipeline.run(square()).unwrap();
+        assert_eq!(result.selected.len(), 1);
+        assert_eq!(result.curves.len(), 1);
+        assert!(result.written.is_empty()); // no export options
+        assert_eq!(result.timings.len(), 4);
+    }
+
+    #[test]
+    fn test_filter_keeps_longest() {
+        let pipeline = Pipeline::new().filter(FilterOptions {
+            max_curves: 2,
+            ..Default::default()
+        });
+        let paths: Vec<Path> = [20, 50, 30]
+            .iter()
+            .map(|&n| (0..n).map(|i| (i, 0)).collect())
+            .collect();
+        let lengths: Vec<usize> = pipeline
+            .filter_paths(&paths, &square())
+            .unwrap()
+            .iter()
+            .map(|p| p.len())
+            .collect();
+        assert_eq!(lengths, vec![50, 30]);
+    }
+
+    #[test]
+    fn test_budget_sees_every_path() {
+        let pipeline = Pipeline::new().fit(FitOptions {
+            max_harmonics: 4,
+            budget: Some(BudgetOptions {
+                total: 10_000,
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+        // more than the 32 paths max_curves keeps by default
+        let paths: Vec<Path> = (0..40)
+            .map(|j| (0..20 + j).map(|i| (i, 2 * j)).collect())
+            .collect();
+        let selected = pipeline.filter_paths(&paths, &square()).unwrap();
+        assert_eq!(selected.len(), 40);
+        let curves = pipeline.fit_curves(&selected);
+        let options = pipeline.fit.budget.as_ref().unwrap();
+        let (kept, curves) = pipeline.spend_budget(&selected, &curves, options);
+        assert_eq!((kept.len(), curves.len()), (40, 40));
+    }
+
+    #[test]
+    fn test_errors() {
+        let blur = Pipeline::new().preprocess(PreprocessOptions { blur_sigma: -1.0 });
+        assert!(matches!(
+            blur.run(square()),
+            Err(Error::InvalidParameter(_))
+        ));
+
+        let canny = Pipeline::new().edges(EdgeOptions {
+            detector: EdgeDetector::Canny {
+                low: 100.0,
+                high: 50.0,
+            },
+        });
+        assert!(matches!(
+            canny.run(square()),
+            Err(Error::InvalidParameter(_))
+        ));
+
+        // a library caller can hand over an image without get_image's check
+        assert!(matches!(
+            Pipeline::new().run(DynamicImage::new_rgba8(0, 0)),
+            Err(Error::EmptyInput(_))
+        ));
+
+        match Pipeline::new().run_file("images/does_not_exist.png") {
+            Err(Error::Io(path, _)) => assert_eq!(path, PathBuf::from("images/does_not_exist.png")),
+            _ => panic!("expected an io error"),
+        }
+    }
+}
@@ -0,0 +1,248 @@
+use crate::error::{Error, Result};
+use crate::img_to_line::Path;
+use crate::model::CurveModel;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write as _;
+
+pub type Polyline = Vec<(f32, f32)>;
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Tool {
+    Pen { up_z: f32, down_z: f32 }, // z heights in mm
+    Laser { power: u32 },           // spindle value passed to M3
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PlotOptions {
+    pub width_mm: f32, // drawing is scaled to fit inside width x height, keeping aspect ratio
+    pub height_mm: f32,
+    pub margin_mm: f32,
+    pub feed_rate: f32,   // drawing speed, mm/min
+    pub travel_rate: f32, // pen up/laser off speed, mm/min
+    pub tool: Tool,
+}
+
+impl Default for PlotOptions {
+    fn default() -> Self {
+        // A4-ish area for an AxiDraw style plotter
+        PlotOptions {
+            width_mm: 280.0,
+            height_mm: 200.0,
+            margin_mm: 10.0,
+            feed_rate: 1500.0,
+            travel_rate: 5000.0,
+            tool: Tool::Pen {
+                up_z: 5.0,
+                down_z: 0.0,
+            },
+        }
+    }
+}
+
+pub fn curves_to_polylines(curves: &[impl CurveModel], samples: usize) -> Vec<Polyline> {
+    curves.iter().map(|c| c.sample(samples)).collect()
+}
+
+pub fn paths_to_polylines(paths: &[Path]) -> Vec<Polyline> {
+    paths
+        .iter()
+        .map(|p| p.iter().map(|&(x, y)| (x as f32, y as f32)).collect())
+        .collect()
+}
+
+fn dist2(a: (f32, f32), b: (f32, f32)) -> f32 {
+    (a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)
+}
+
+// image coordinates of the machine's origin, the bottom left corner since y is flipped
+pub fn home((_, height): (u32, u32)) -> (f32, f32) {
+    (0.0, height as f32)
+}
+
+// greedy nearest neighbour ordering from `start`, in image coordinates, reversing a polyline when
+// its end is closer than its start
+pub fn order_polylines(polylines: &[Polyline], start: (f32, f32)) -> Vec<Polyline> {
+    let mut remaining: Vec<&Polyline> = polylines.iter().filter(|p| !p.is_empty()).collect();
+    let mut ordered = Vec::with_capacity(remaining.len());
+    let mut pos = start;
+
+    while !remaining.is_empty() {
+        let mut best = (0, false, f32::MAX);
+        for (i, p) in remaining.iter().enumerate() {
+            let to_start = dist2(pos, p[0]);
+            let to_end = dist2(pos, p[p.len() - 1]);
+            if to_start < best.2 {
+                best = (i, false, to_start);
+            }
+            if to_end < best.2 {
+                best = (i, true, to_end);
+            }
+        }
+        let mut next = remaining.swap_remove(best.0).clone();
+        if best.1 {
+            next.reverse();
+        }
+        pos = next[next.len() - 1];
+        ordered.push(next);
+    }
+    ordered
+}
+
+pub fn travel_distance(polylines: &[Polyline], start: (f32, f32)) -> f32 {
+    let mut pos = start;
+    let mut total = 0.0;
+    for p in polylines.iter().filter(|p| !p.is_empty()) {
+        total += dist2(pos, p[0]).sqrt();
+        pos = p[p.len() - 1];
+    }
+    total
+}
+
+// image pixels to machine mm, flipping y since machines have the origin at the bottom left
+fn to_mm(
+    polylines: &[Polyline],
+    (width, height): (u32, u32),
+    options: &PlotOptions,
+) -> Vec<Polyline> {
+    let avail_w = options.width_mm - 2.0 * options.margin_mm;
+    let avail_h = options.height_mm - 2.0 * options.margin_mm;
+    let scale = (avail_w / width.max(1) as f32).min(avail_h / height.max(1) as f32);
+    polylines
+        .iter()
+        .map(|p| {
+            p.iter()
+                .map(|&(x, y)| {
+                    (
+                        options.margin_mm + x * scale,
+                        options.margin_mm + (height as f32 - y) * scale,
+                    )
+                })
+                .collect()
+        })
+        .collect()
+}
+
+pub fn to_gcode(polylines: &[Polyline], dims: (u32, u32), options: &PlotOptions) -> String {
+    let (tool_up, tool_down) = match options.tool {
+        Tool::Pen { up_z, down_z } => (
+            format!("G0 Z{up_z:.3}"),
+            format!("G1 Z{down_z:.3} F{:.0}", options.feed_rate),
+        ),
+        Tool::Laser { power } => ("M5".to_string(), format!("M3 S{power}")),
+    };
+
+    let mut gcode = String::new();
+    gcode.push_str("G21 ; mm\nG90 ; absolute positioning\n");
+    let _ = writeln!(gcode, "{tool_up}");
+
+    for p in to_mm(&order_polylines(polylines, home(dims)), dims, options).iter() {
+        let _ = writeln!(
+            gcode,
+            "G0 X{:.3} Y{:.3} F{:.0}",
+            p[0].0, p[0].1, options.travel_rate
+        );
+        let _ = writeln!(gcode, "{tool_down}");
+        let _ = writeln!(gcode, "G1 F{:.0}", options.feed_rate);
+        for (x, y) in p.iter().skip(1) {
+            let _ = writeln!(gcode, "G1 X{x:.3} Y{y:.3}");
+        }
+        let _ = writeln!(gcode, "{tool_up}");
+    }
+    let _ = writeln!(gcode, "G0 X0 Y0 F{:.0}\nM2", options.travel_rate);
+    gcode
+}
+
+// HPGL uses plotter units of 0.025mm, and only has a pen down speed (VS, cm/s)
+pub fn to_hpgl(polylines: &[Polyline], dims: (u32, u32), options: &PlotOptions) -> String {
+    let unit = |v: f32| (v * 40.0).round() as i32;
+    let mut hpgl = String::new();
+    let _ = write!(hpgl, "IN;SP1;VS{:.1};", options.feed_rate / 600.0);
+
+    for p in to_mm(&order_polylines(polylines, home(dims)), dims, options).iter() {
+        let _ = write!(hpgl, "PU{},{};", unit(p[0].0), unit(p[0].1));
+        if p.len() > 1 {
+            let coords = p
+                .iter()
+                .skip(1)
+                .map(|&(x, y)| format!("{},{}", unit(x), unit(y)))
+                .collect::<Vec<_>>()
+                .join(",");
+            let _ = write!(hpgl, "PD{coords};");
+        }
+    }
+    hpgl.push_str("PU;SP0;\n");
+    hpgl
+}
+
+pub fn save_gcode(
+    file: impl AsRef<std::path::Path>,
+    polylines: &[Polyline],
+    dims: (u32, u32),
+    options: &PlotOptions,
+) -> Result<()> {
+    std::fs::write(&file, to_gcode(polylines, dims, options)).map_err(Error::io(&file))
+}
+
+pub fn save_hpgl(
+    file: impl AsRef<std::path::Path>,
+    polylines: &[Polyline],
+    dims: (u32, u32),
+    options: &PlotOptions,
+) -> Result<()> {
+    std::fs::write(&file, to_hpgl(polylines, dims, options)).map_err(Error::io(&file))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_order_polylines() {
+        let polylines = vec![
+            vec![(100.0, 100.0), (90.0, 90.0)],
+            vec![(10.0, 10.0), (50.0, 50.0)],
+            vec![(1.0, 1.0), (5.0, 5.0)],
+        ];
+        let ordered = order_polylines(&polylines, (0.0, 0.0));
+        assert_eq!(ordered[0], vec![(1.0, 1.0), (5.0, 5.0)]);
+        assert_eq!(ordered[1], vec![(10.0, 10.0), (50.0, 50.0)]);
+        assert_eq!(ordered[2], vec![(90.0, 90.0), (100.0, 100.0)]); // reversed
+        let start = (0.0, 0.0);
+        assert!(travel_distance(&ordered, start) < travel_distance(&polylines, start));
+
+        // the machine starts at the bottom left of the image, not its top left
+        let polylines = vec![vec![(0.0, 0.0), (5.0, 0.0)], vec![(0.0, 95.0), (5.0, 95.0)]];
+        let ordered = order_polylines(&polylines, home((100, 100)));
+        assert_eq!(ordered[0], polylines[1]);
+    }
+
+    #[test]
+    fn test_gcode_pen_moves() {
+        let polylines = vec![
+            vec![(0.0, 0.0), (10.0, 10.0)],
+            vec![(20.0, 0.0), (30.0, 0.0)],
+        ];
+        let gcode = to_gcode(&polylines, (30, 10), &PlotOptions::default());
+        assert_eq!(gcode.matches("G1 Z0.000").count(), 2);
+        assert_eq!(gcode.matches("G0 Z5.000").count(), 3);
+
+        let options = PlotOptions {
+            tool: Tool::Laser { power: 255 },
+            ..Default::default()
+        };
+        let gcode = to_gcode(&polylines, (30, 10), &options);
+        assert_eq!(gcode.matches("M3 S255").count(), 2);
+    }
+
+    #[test]
+    fn test_hpgl_fits_page() {
+        let polylines = vec![vec![(0.0, 0.0), (1000.0, 500.0)]];
+        let options = PlotOptions::default();
+        let hpgl = to_hpgl(&polylines, (1000, 500), &options);
+        // 1000px wide scaled to 260mm of drawable width
+        assert!(hpgl.contains("PU400,"));
+        assert!(hpgl.contains("PD10800,400;"));
+    }
+}
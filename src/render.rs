@@ -0,0 +1,116 @@
+use crate::draw::draw_line_aa;
+use crate::error::{Error, Result};
+use crate::model::CurveModel;
+use image::{DynamicImage, Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RenderOptions {
+    #[serde(with = "crate::config::hex_colour")]
+    pub stroke: Rgba<u8>,
+    #[serde(with = "crate::config::hex_colour")]
+    pub background: Rgba<u8>,
+    pub original_opacity: f32, // how much of the source image shows through, 0 to hide it
+    pub samples_per_px: f32,   // curve samples per pixel of (estimated) curve length
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions {
+            stroke: Rgba([255, 255, 255, 255]),
+            background: Rgba([0, 0, 0, 255]),
+            original_opacity: 0.25,
+            samples_per_px: 2.0,
+        }
+    }
+}
+
+// dense enough that consecutive samples are well under a pixel apart
+fn sample_count(curve: &impl CurveModel, samples_per_px: f32) -> usize {
+    let coarse = curve.sample(256);
+    let len: f32 = coarse
+        .windows(2)
+        .map(|w| ((w[1].0 - w[0].0).powi(2) + (w[1].1 - w[0].1).powi(2)).sqrt())
+        .sum();
+    ((len * samples_per_px) as usize).clamp(256, 1 << 16)
+}
+
+fn background(
+    (width, height): (u32, u32),
+    original: Option<&DynamicImage>,
+    options: &RenderOptions,
+) -> RgbaImage {
+    let mut img = RgbaImage::from_pixel(width, height, options.background);
+    if let Some(original) = original {
+        let a = options.original_opacity.clamp(0.0, 1.0);
+        let original = original.to_rgba8();
+        for (x, y, p) in img.enumerate_pixels_mut() {
+            if x < original.width() && y < original.height() {
+                let o = original.get_pixel(x, y);
+                for c in 0..3 {
+                    p[c] = (p[c] as f32 * (1.0 - a) + o[c] as f32 * a).round() as u8;
+                }
+            }
+        }
+    }
+    img
+}
+
+pub fn render_curves(
+    dims: (u32, u32),
+    curves: &[impl CurveModel],
+    original: Option<&DynamicImage>,
+    options: &RenderOptions,
+) -> RgbaImage {
+    let mut img = background(dims, original, options);
+    for curve in curves.iter() {
+        let points = curve.sample(sample_count(curve, options.samples_per_px));
+        for w in points.windows(2) {
+            draw_line_aa(&mut img, w[0], w[1], options.stroke);
+        }
+    }
+    img
+}
+
+pub fn save_render(
+    file: impl AsRef<std::path::Path>,
+    dims: (u32, u32),
+    curves: &[impl CurveModel],
+    original: Option<&DynamicImage>,
+    options: &RenderOptions,
+) -> Result<()> {
+    render_curves(dims, curves, original, options)
+        .save(&file)
+        .map_err(Error::image(&file))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fourier::FourierSeries;
+
+    #[test]
+    fn test_render_follows_line() {
+        let line: Vec<(i32, i32)> = (5..45).map(|i| (i, 20)).collect();
+        let curve = FourierSeries::fit(&line);
+        let img = render_curves((50, 40), &[curve], None, &RenderOptions::default());
+        assert_eq!(img.dimensions(), (50, 40));
+        assert!(img.get_pixel(25, 20)[0] > 128);
+        assert_eq!(img.get_pixel(25, 5)[0], 0);
+    }
+
+    #[test]
+    fn test_render_fades_original() {
+        let original =
+            DynamicImage::ImageRgba8(RgbaImage::from_pixel(8, 8, Rgba([200, 200, 200, 255])));
+        let no_curves: &[FourierSeries] = &[];
+        let img = render_curves(
+            (8, 8),
+            no_curves,
+            Some(&original),
+            &RenderOptions::default(),
+        );
+        assert_eq!(img.get_pixel(3, 3)[0], 50);
+    }
+}
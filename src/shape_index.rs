@@ -0,0 +1,162 @@
+use crate::descriptors::{self, EllipticDescriptors, EllipticOptions};
+use crate::error::{Error, Result};
+use crate::img_to_line::{self, Path};
+use crate::pipeline::{Pipeline, Stage};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Shape {
+    pub name: String,
+    pub source: Option<PathBuf>, // image it was traced from
+    pub descriptors: EllipticDescriptors,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Match {
+    pub name: String,
+    pub source: Option<PathBuf>,
+    pub distance: f32,
+}
+
+// reference outlines to look contours up in, e.g. traced from a folder of icons
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ShapeIndex {
+    pub options: EllipticOptions, // every shape and query is described with these
+    pub shapes: Vec<Shape>,
+}
+
+// the shape an image stands for is its longest traced path
+pub fn longest_path(pipeline: &Pipeline, file: &std::path::Path) -> Result<Path> {
+    let img = img_to_line::get_image(&file.to_string_lossy())?;
+    let result = pipeline.run_until(img, Stage::Filter)?;
+    let longest = result.paths.into_iter().max_by_key(|p| p.len());
+    longest.ok_or_else(|| Error::EmptyInput(format!("no paths traced in {}", file.display())))
+}
+
+impl ShapeIndex {
+    pub fn new(options: EllipticOptions) -> ShapeIndex {
+        ShapeIndex {
+            options,
+            shapes: vec![],
+        }
+    }
+
+    pub fn add(&mut self, name: impl Into<String>, source: Option<PathBuf>, contour: &Path) {
+        self.shapes.push(Shape {
+            name: name.into(),
+            source,
+            descriptors: descriptors::path_descriptors(contour, &self.options),
+        });
+    }
+
+    // adds the longest path of each image, named after the file. images that cannot be read or
+    // have nothing to trace are skipped and their errors returned
+    pub fn add_images(&mut self, pipeline: &Pipeline, files: &[PathBuf]) -> Vec<Error> {
+        let mut errors = vec![];
+        for file in files.iter() {
+            match longest_path(pipeline, file) {
+                Ok(path) => {
+                    let name = file.file_stem().unwrap_or_default().to_string_lossy();
+                    self.add(name, Some(file.clone()), &path);
+                }
+                Err(e) => errors.push(e),
+            }
+        }
+        errors
+    }
+
+    // the k shapes closest to `contour`, nearest first
+    pub fn query(&self, contour: &Path, k: usize) -> Vec<Match> {
+        let query = descriptors::path_descriptors(contour, &self.options);
+        let mut matches: Vec<Match> = self
+            .shapes
+            .iter()
+            .map(|shape| Match {
+                name: shape.name.clone(),
+                source: shape.source.clone(),
+                distance: query.distance(&shape.descriptors),
+            })
+            .collect();
+        matches.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+        matches.truncate(k);
+        matches
+    }
+
+    pub fn save(&self, file: impl AsRef<std::path::Path>) -> Result<()> {
+        let json = serde_json::to_string_pretty(self).unwrap();
+        std::fs::write(&file, json).map_err(Error::io(&file))
+    }
+
+    pub fn load(file: impl AsRef<std::path::Path>) -> Result<ShapeIndex> {
+        let file = file.as_ref();
+        let json = std::fs::read_to_string(file).map_err(Error::io(file))?;
+        serde_json::from_str(&json).map_err(|e| Error::Parse(file.to_path_buf(), e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config;
+
+    fn polygon(corners: &[(i32, i32)]) -> Path {
+        let mut path = vec![];
+        for (i, &(x0, y0)) in corners.iter().enumerate() {
+            let (x1, y1) = corners[(i + 1) % corners.len()];
+            let steps = (x1 - x0).abs().max((y1 - y0).abs());
+            for s in 0..steps {
+                path.push((x0 + (x1 - x0) * s / steps, y0 + (y1 - y0) * s / steps));
+            }
+        }
+        path
+    }
+
+    #[test]
+    fn test_query_nearest() {
+        let mut index = ShapeIndex::new(EllipticOptions::default());
+        index.add(
+            "card",
+            None,
+            &polygon(&[(0, 0), (60, 0), (60, 40), (0, 40)]),
+        );
+        index.add("triangle", None, &polygon(&[(0, 0), (60, 0), (30, 50)]));
+        index.add("bar", None, &polygon(&[(0, 0), (80, 0), (80, 10), (0, 10)]));
+
+        // bigger, moved, on its side and starting from another corner
+        let card = polygon(&[(200, 100), (200, 220), (120, 220), (120, 100)]);
+        let matches = index.query(&card, 2);
+        assert_eq!(matches.len(), 2);
+        assert_eq!(matches[0].name, "card");
+        assert!(matches[0].distance < 0.05, "{matches:?}");
+        assert!(matches[1].distance > matches[0].distance);
+
+        let file = std::env::temp_dir().join(format!("shape_index_{}.json", std::process::id()));
+        index.save(&file).unwrap();
+        let loaded = ShapeIndex::load(&file).unwrap();
+        assert_eq!(loaded.query(&card, 2), matches);
+        std::fs::write(&file, "{\"shapes\": 3}").unwrap();
+        assert!(matches!(ShapeIndex::load(&file), Err(Error::Parse(..))));
+        std::fs::remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn test_checkmark_drawn() {
+        let pipeline = config::preset("line-art").unwrap();
+        let files = [
+            "images/checkmark.png",
+            "images/smile.png",
+            "images/missing.png",
+            "images/islands1.png",
+        ];
+        let mut index = ShapeIndex::new(EllipticOptions::default());
+        let errors = index.add_images(&pipeline, &files.map(PathBuf::from));
+        assert_eq!(errors.len(), 1);
+        assert_eq!(index.shapes.len(), 3);
+
+        let drawn = longest_path(&pipeline, "images/checkmark.png".as_ref()).unwrap();
+        let matches = index.query(&drawn, 3);
+        assert_eq!(matches[0].name, "checkmark");
+        assert!(matches[0].distance < 1e-4);
+    }
+}
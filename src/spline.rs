@@ -0,0 +1,345 @@
+use crate::img_to_line::{self, Path};
+use serde::{Deserialize, Serialize};
+
+// Cubic splines through the corners of the simplified path, for smooth strokes that a Fourier
+// series needs many terms for. Every segment is a cubic in u from 0 at one knot to 1 at the next.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SplineKind {
+    Natural,    // continuous curvature, zero at the ends
+    CatmullRom, // centripetal, so no cusps or loops between knots that are close together
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SplineOptions {
+    pub kind: SplineKind,
+    pub epsilon: f32, // Ramer-Douglas-Peucker tolerance the knots are picked with, in pixels
+}
+
+impl Default for SplineOptions {
+    fn default() -> Self {
+        SplineOptions {
+            kind: SplineKind::CatmullRom,
+            epsilon: 1.5,
+        }
+    }
+}
+
+// coefficients of 1, u, u^2, u^3
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Segment {
+    pub x: [f32; 4],
+    pub y: [f32; 4],
+}
+
+// segments[i] goes from knot i to knot i + 1, each takes an equal share of t in [0, 1]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spline {
+    pub segments: Vec<Segment>,
+}
+
+fn cubic(c: &[f32; 4], u: f32) -> f32 {
+    c[0] + u * (c[1] + u * (c[2] + u * c[3]))
+}
+
+// the polynomial in `var`, leaving out zero coefficients
+fn polynomial(c: &[f32; 4], var: &str, power: impl Fn(usize) -> String) -> String {
+    let terms: Vec<String> = c
+        .iter()
+        .enumerate()
+        .filter(|&(p, &c)| p == 0 || c != 0.0)
+        .map(|(p, c)| match p {
+            0 => format!("{c}"),
+            1 => format!("{c}{var}"),
+            _ => format!("{c}{var}{}", power(p)),
+        })
+        .collect();
+    terms.join("+")
+}
+
+impl Segment {
+    // cubic Hermite segment from p0 to p1 with tangents m0 and m1
+    fn hermite(p0: (f32, f32), p1: (f32, f32), m0: (f32, f32), m1: (f32, f32)) -> Segment {
+        let coefficients = |p0: f32, p1: f32, m0: f32, m1: f32| {
+            [
+                p0,
+                m0,
+                3.0 * (p1 - p0) - 2.0 * m0 - m1,
+                2.0 * (p0 - p1) + m0 + m1,
+            ]
+        };
+        Segment {
+            x: coefficients(p0.0, p1.0, m0.0, m1.0),
+            y: coefficients(p0.1, p1.1, m0.1, m1.1),
+        }
+    }
+
+    pub fn evaluate(&self, u: f32) -> (f32, f32) {
+        (cubic(&self.x, u), cubic(&self.y, u))
+    }
+}
+
+impl Spline {
+    pub fn evaluate(&self, t: f32) -> (f32, f32) {
+        let n = self.segments.len();
+        if n == 0 {
+            return (0.0, 0.0);
+        }
+        let s = t.clamp(0.0, 1.0) * n as f32;
+        let i = (s as usize).min(n - 1);
+        self.segments[i].evaluate(s - i as f32)
+    }
+
+    pub fn sample(&self, n: usize) -> Vec<(f32, f32)> {
+        let steps = n.max(2) - 1;
+        (0..=steps)
+            .map(|i| self.evaluate(i as f32 / steps as f32))
+            .collect()
+    }
+
+    // numbers it takes to write down, to compare with FourierSeries::parameters
+    pub fn parameters(&self) -> usize {
+        self.segments.len() * 8
+    }
+
+    // one Desmos expression per segment, t in [0, 1] on each, y negated like
+    // FourierSeries::to_equation
+    pub fn to_equations(&self) -> Vec<String> {
+        let power = |p: usize| format!("^{p}");
+        self.segments
+            .iter()
+            .map(|s| {
+                format!(
+                    "(({}),-({}))",
+                    polynomial(&s.x, "t", power),
+                    polynomial(&s.y, "t", power)
+                )
+            })
+            .collect()
+    }
+
+    // x(t) and y(t) as LaTeX cases over t in [0, n], segment i taking t from i to i + 1
+    pub fn to_latex(&self) -> String {
+        let power = |p: usize| format!("^{{{p}}}");
+        let cases = |name: &str, coefficients: &dyn Fn(&Segment) -> [f32; 4]| {
+            let n = self.segments.len();
+            let rows: Vec<String> = self
+                .segments
+                .iter()
+                .enumerate()
+                .map(|(i, s)| {
+                    let var = if i == 0 {
+                        "t".to_string()
+                    } else {
+                        format!("(t-{i})")
+                    };
+                    let bound = if i + 1 == n { "\\le" } else { "<" };
+                    let poly = polynomial(&coefficients(s), &var, power).replace("+-", "-");
+                    format!("  {poly} & {i} \\le t {bound} {} \\\\", i + 1)
+                })
+                .collect();
+            format!(
+                "{name}(t) = \\begin{{cases}}\n{}\n\\end{{cases}}",
+                rows.join("\n")
+            )
+        };
+        // image y points down
+        let flipped = |s: &Segment| s.y.map(|c| -c);
+        format!("{}\n{}\n", cases("x", &|s| s.x), cases("y", &flipped))
+    }
+}
+
+// second derivatives at the knots of the natural spline through `values` with a unit step
+// between knots, zero at both ends. thomas algorithm on M[i-1] + 4 M[i] + M[i+1] = rhs[i]
+fn natural_second_derivatives(values: &[f32]) -> Vec<f32> {
+    let n = values.len();
+    let mut m = vec![0.0; n];
+    if n < 3 {
+        return m;
+    }
+    let inner = n - 2;
+    let (mut c, mut d) = (vec![0.0; inner], vec![0.0; inner]);
+    for i in 0..inner {
+        let rhs = 6.0 * (values[i + 2] - 2.0 * values[i + 1] + values[i]);
+        let (prev_c, prev_d) = if i == 0 {
+            (0.0, 0.0)
+        } else {
+            (c[i - 1], d[i - 1])
+        };
+        let denominator = 4.0 - prev_c;
+        c[i] = 1.0 / denominator;
+        d[i] = (rhs - prev_d) / denominator;
+    }
+    for i in (0..inner).rev() {
+        let next = m[i + 2];
+        m[i + 1] = d[i] - c[i] * next;
+    }
+    m
+}
+
+fn natural(knots: &[(f32, f32)]) -> Vec<Segment> {
+    let xs: Vec<f32> = knots.iter().map(|p| p.0).collect();
+    let ys: Vec<f32> = knots.iter().map(|p| p.1).collect();
+    let (mx, my) = (
+        natural_second_derivatives(&xs),
+        natural_second_derivatives(&ys),
+    );
+    let coefficients = |v: &[f32], m: &[f32], i: usize| {
+        [
+            v[i],
+            v[i + 1] - v[i] - (2.0 * m[i] + m[i + 1]) / 6.0,
+            m[i] / 2.0,
+            (m[i + 1] - m[i]) / 6.0,
+        ]
+    };
+    (0..knots.len() - 1)
+        .map(|i| Segment {
+            x: coefficients(&xs, &mx, i),
+            y: coefficients(&ys, &my, i),
+        })
+        .collect()
+}
+
+// Barry and Goldman's centripetal Catmull-Rom as Hermite segments, the knot spacing is the square
+// root of the distance and the ends are extended by reflecting their neighbours
+fn catmull_rom(knots: &[(f32, f32)]) -> Vec<Segment> {
+    let n = knots.len();
+    let reflect = |a: (f32, f32), b: (f32, f32)| (2.0 * a.0 - b.0, 2.0 * a.1 - b.1);
+    let mut points = Vec::with_capacity(n + 2);
+    points.push(reflect(knots[0], knots[1]));
+    points.extend_from_slice(knots);
+    points.push(reflect(knots[n - 1], knots[n - 2]));
+
+    let spacing = |a: (f32, f32), b: (f32, f32)| (b.0 - a.0).hypot(b.1 - a.1).sqrt().max(1e-6);
+    let slope = |a: (f32, f32), b: (f32, f32), dt: f32| ((b.0 - a.0) / dt, (b.1 - a.1) / dt);
+    // tangent at p1 per unit of the knot parameter
+    let tangent = |p0: (f32, f32), p1: (f32, f32), p2: (f32, f32)| {
+        let (d0, d1) = (spacing(p0, p1), spacing(p1, p2));
+        let (a, b, c) = (slope(p0, p1, d0), slope(p0, p2, d0 + d1), slope(p1, p2, d1));
+        (a.0 - b.0 + c.0, a.1 - b.1 + c.1)
+    };
+    points
+        .windows(4)
+        .map(|w| {
+            let dt = spacing(w[1], w[2]);
+            let m1 = tangent(w[0], w[1], w[2]);
+            let m2 = tangent(w[1], w[2], w[3]);
+            Segment::hermite(w[1], w[2], (m1.0 * dt, m1.1 * dt), (m2.0 * dt, m2.1 * dt))
+        })
+        .collect()
+}
+
+pub fn fit_points(knots: &[(f32, f32)], kind: SplineKind) -> Spline {
+    if knots.len() < 2 {
+        let p = knots.first().copied().unwrap_or_default();
+        let point = Segment {
+            x: [p.0, 0.0, 0.0, 0.0],
+            y: [p.1, 0.0, 0.0, 0.0],
+        };
+        return Spline {
+            segments: vec![point],
+        };
+    }
+    let segments = match kind {
+        SplineKind::Natural => natural(knots),
+        SplineKind::CatmullRom => catmull_rom(knots),
+    };
+    Spline { segments }
+}
+
+// through the points Ramer-Douglas-Peucker keeps
+pub fn fit(path: &Path, options: &SplineOptions) -> Spline {
+    let mut knots = img_to_line::simplify(path, options.epsilon);
+    knots.dedup();
+    let knots: Vec<(f32, f32)> = knots.iter().map(|&(x, y)| (x as f32, y as f32)).collect();
+    fit_points(&knots, options.kind)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fourier::FourierSeries;
+
+    fn arc() -> Path {
+        let mut path: Path = (0..=300)
+            .map(|i| {
+                let a = (i as f32 / 300.0) * std::f32::consts::PI;
+                (
+                    (60.0 + 50.0 * a.cos()).round() as i32,
+                    (60.0 - 50.0 * a.sin()).round() as i32,
+                )
+            })
+            .collect();
+        path.dedup();
+        path
+    }
+
+    #[test]
+    fn test_through_knots() {
+        let knots = [
+            (0.0, 0.0),
+            (10.0, 5.0),
+            (20.0, 0.0),
+            (25.0, 10.0),
+            (40.0, 12.0),
+        ];
+        for kind in [SplineKind::Natural, SplineKind::CatmullRom] {
+            let spline = fit_points(&knots, kind);
+            assert_eq!(spline.segments.len(), 4);
+            for (i, knot) in knots.iter().enumerate() {
+                let p = spline.evaluate(i as f32 / 4.0);
+                assert!((p.0 - knot.0).abs() < 1e-3 && (p.1 - knot.1).abs() < 1e-3);
+            }
+        }
+        // the natural spline has continuous curvature and none at the ends
+        let natural = fit_points(&knots, SplineKind::Natural);
+        for pair in natural.segments.windows(2) {
+            let end = 2.0 * pair[0].x[2] + 6.0 * pair[0].x[3];
+            assert!((end - 2.0 * pair[1].x[2]).abs() < 1e-3);
+        }
+        assert!(natural.segments[0].x[2].abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_arc_fewer_terms_than_fourier() {
+        let path = arc();
+        let spline = fit(&path, &SplineOptions::default());
+        for &(x, y) in path.iter() {
+            let closest = spline
+                .sample(2000)
+                .iter()
+                .map(|p| (p.0 - x as f32).hypot(p.1 - y as f32))
+                .fold(f32::INFINITY, f32::min);
+            assert!(closest < 2.5, "({x}, {y}) {closest}");
+        }
+        let series = FourierSeries::fit(&path);
+        assert!(spline.parameters() < series.parameters());
+    }
+
+    #[test]
+    fn test_exports() {
+        let spline = Spline {
+            segments: vec![
+                Segment {
+                    x: [0.0, 1.0, 0.0, 0.5],
+                    y: [2.0, 0.0, -1.0, 0.0],
+                },
+                Segment {
+                    x: [1.5, 2.0, 0.0, 0.0],
+                    y: [1.0, 1.0, 0.0, 0.0],
+                },
+            ],
+        };
+        assert_eq!(
+            spline.to_equations(),
+            vec!["((0+1t+0.5t^3),-(2+-1t^2))", "((1.5+2t),-(1+1t))"]
+        );
+        let latex = spline.to_latex();
+        assert!(latex.starts_with("x(t) = \\begin{cases}\n  0+1t+0.5t^{3} & 0 \\le t < 1 \\\\"));
+        assert!(latex.contains("  1.5+2(t-1) & 1 \\le t \\le 2 \\\\"));
+        assert!(latex.contains("  -2+1t^{2} & 0 \\le t < 1"));
+        assert_eq!(spline.parameters(), 16);
+    }
+}
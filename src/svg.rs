@@ -0,0 +1,158 @@
+use crate::bezier::BezierCurve;
+use crate::error::{Error, Result};
+use crate::img_to_line::Path;
+use crate::model::CurveModel;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write as _;
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SvgOptions {
+    pub paths: bool,  // raw traced paths as polylines
+    pub curves: bool, // fitted curves as paths
+    pub samples: usize,
+    pub stroke_width: f32,
+    pub path_colour: String,
+    pub curve_colour: String,
+    pub bezier_colour: String,
+}
+
+impl Default for SvgOptions {
+    fn default() -> Self {
+        SvgOptions {
+            paths: true,
+            curves: true,
+            samples: 512,
+            stroke_width: 1.0,
+            path_colour: "#808080".to_string(),
+            curve_colour: "#e0218a".to_string(),
+            bezier_colour: "#1f77b4".to_string(),
+        }
+    }
+}
+
+// each layer is an Inkscape layer so they can be toggled separately
+fn open_layer(svg: &mut String, id: &str, label: &str, colour: &str, width: f32) {
+    let _ = writeln!(
+        svg,
+        r#"  <g id="{id}" inkscape:groupmode="layer" inkscape:label="{label}" fill="none" stroke="{colour}" stroke-width="{width}" stroke-linecap="round" stroke-linejoin="round">"#
+    );
+}
+
+pub fn to_svg(
+    (width, height): (u32, u32),
+    paths: &[Path],
+    curves: &[impl CurveModel],
+    beziers: &[BezierCurve],
+    options: &SvgOptions,
+) -> String {
+    let mut svg = String::new();
+    svg.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:inkscape="http://www.inkscape.org/namespaces/inkscape" width="{width}" height="{height}" viewBox="0 0 {width} {height}">"#
+    );
+
+    if options.paths {
+        open_layer(
+            &mut svg,
+            "paths",
+            "Traced paths",
+            &options.path_colour,
+            options.stroke_width,
+        );
+        for path in paths.iter() {
+            let points = path
+                .iter()
+                .map(|(x, y)| format!("{x},{y}"))
+                .collect::<Vec<_>>()
+                .join(" ");
+            let _ = writeln!(svg, r#"    <polyline points="{points}"/>"#);
+        }
+        svg.push_str("  </g>\n");
+    }
+
+    if options.curves {
+        open_layer(
+            &mut svg,
+            "curves",
+            "Fitted curves",
+            &options.curve_colour,
+            options.stroke_width,
+        );
+        for curve in curves.iter() {
+            let d = curve.to_svg_path(options.samples);
+            let _ = writeln!(svg, r#"    <path d="{d}"/>"#);
+        }
+        svg.push_str("  </g>\n");
+    }
+
+    // only there when the Bézier segments were fitted
+    if !beziers.is_empty() {
+        open_layer(
+            &mut svg,
+            "beziers",
+            "Bézier curves",
+            &options.bezier_colour,
+            options.stroke_width,
+        );
+        for curve in beziers.iter() {
+            let _ = writeln!(svg, r#"    <path d="{}"/>"#, curve.to_svg_path());
+        }
+        svg.push_str("  </g>\n");
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+pub fn save_svg(
+    file: impl AsRef<std::path::Path>,
+    dims: (u32, u32),
+    paths: &[Path],
+    curves: &[impl CurveModel],
+    beziers: &[BezierCurve],
+    options: &SvgOptions,
+) -> Result<()> {
+    let svg = to_svg(dims, paths, curves, beziers, options);
+    std::fs::write(&file, svg).map_err(Error::io(&file))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fourier::FourierSeries;
+
+    #[test]
+    fn test_svg_layers() {
+        let path: Path = (0..20).map(|i| (i, i)).collect();
+        let curve = FourierSeries::fit(&path);
+        let bezier = crate::bezier::fit(&path, &Default::default());
+        let svg = to_svg((64, 32), &[path], &[curve], &[], &SvgOptions::default());
+        assert!(svg.contains(r#"viewBox="0 0 64 32""#));
+        assert!(svg.contains("<polyline points=\"0,0 1,1 2,2"));
+        assert_eq!(svg.matches("<path d=\"M").count(), 1);
+        assert!(!svg.contains(r#"id="beziers""#));
+
+        let svg = to_svg(
+            (64, 32),
+            &[],
+            &[] as &[FourierSeries],
+            &[bezier],
+            &SvgOptions::default(),
+        );
+        assert!(svg.contains(r#"<path d="M0.00 0.00 C"#));
+    }
+
+    #[test]
+    fn test_svg_curves_only() {
+        let path: Path = (0..20).map(|i| (i, 0)).collect();
+        let options = SvgOptions {
+            paths: false,
+            ..Default::default()
+        };
+        let svg = to_svg((20, 20), &[path], &[] as &[FourierSeries], &[], &options);
+        assert!(!svg.contains("polyline"));
+        assert!(svg.contains(r#"id="curves""#));
+    }
+}
@@ -0,0 +1,177 @@
+// runs the pipeline on the bundled images and compares against tests/golden/<case>.json.
+// counts are exact, the fit errors may not get worse than recorded, and the rendered outputs
+// are compared by hash. after an intended change in behaviour, write new golden files with
+//     BLESS=1 cargo test --test golden
+// and check the diff of tests/golden before committing them
+use image::{DynamicImage, GenericImageView};
+use line_to_equation::pipeline::{ExportOptions, Stage};
+use line_to_equation::render::{self, RenderOptions};
+use line_to_equation::svg::{self, SvgOptions};
+use line_to_equation::{config, img_to_line, metrics, Pipeline};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+// how much the fit errors may drift before the golden results need blessing
+const ERROR_SLACK: f32 = 0.01;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Golden {
+    paths: usize,
+    path_length: usize, // points over all traced paths
+    curves: usize,
+    hausdorff: f32,
+    chamfer: f32,
+    f1: f32,
+    max_curve_rms: f32,
+    edges_hash: String,
+    equations_hash: String,
+    svg_hash: String,
+    render_hash: String,
+}
+
+// 64 bit FNV-1a, unlike std's hasher it is the same on every platform and release
+fn hash(bytes: &[u8]) -> String {
+    let hash = bytes.iter().fold(0xcbf29ce484222325u64, |h, &b| {
+        (h ^ b as u64).wrapping_mul(0x100000001b3)
+    });
+    format!("{hash:016x}")
+}
+
+fn measure(pipeline: &Pipeline, img: DynamicImage) -> Golden {
+    let result = pipeline.run_until(img, Stage::Fit).unwrap();
+    let dims = result.image.dimensions();
+    let m = metrics::evaluate(
+        &result.edges,
+        pipeline.trace.colour,
+        &result.selected,
+        &result.curves,
+        ExportOptions::default().tolerance,
+    );
+    let equations: Vec<String> = result.curves.iter().map(|c| c.to_equation()).collect();
+    let svg = svg::to_svg(
+        dims,
+        &result.selected,
+        &result.curves,
+        &result.beziers,
+        &SvgOptions::default(),
+    );
+    let render = render::render_curves(
+        dims,
+        &result.curves,
+        Some(&result.image),
+        &RenderOptions::default(),
+    );
+    Golden {
+        paths: result.paths.len(),
+        path_length: result.paths.iter().map(|p| p.len()).sum(),
+        curves: result.curves.len(),
+        hausdorff: m.hausdorff,
+        chamfer: m.chamfer,
+        f1: m.f1,
+        max_curve_rms: m.curve_rms.iter().copied().fold(0.0, f32::max),
+        edges_hash: hash(result.edges.as_bytes()),
+        equations_hash: hash(equations.join("\n").as_bytes()),
+        svg_hash: hash(svg.as_bytes()),
+        render_hash: hash(render.as_raw()),
+    }
+}
+
+fn check(case: &str, image: &str, pipeline: Pipeline) {
+    let img = img_to_line::get_image(&format!("images/{image}")).unwrap();
+    let actual = measure(&pipeline, img);
+    assert!(actual.curves > 0, "{case}: nothing was fitted");
+
+    let file = PathBuf::from(format!("tests/golden/{case}.json"));
+    if std::env::var_os("BLESS").is_some() {
+        let json = serde_json::to_string_pretty(&actual).unwrap();
+        std::fs::write(&file, json + "\n").unwrap();
+        return;
+    }
+    let text = std::fs::read_to_string(&file)
+        .unwrap_or_else(|e| panic!("{}: {e}, run with BLESS=1 to create it", file.display()));
+    let golden: Golden = serde_json::from_str(&text).unwrap();
+    let bless = "run with BLESS=1 if this change is intended";
+
+    assert_eq!(actual.paths, golden.paths, "{case}: traced paths, {bless}");
+    assert_eq!(
+        actual.path_length, golden.path_length,
+        "{case}: total path length, {bless}"
+    );
+    assert_eq!(
+        actual.curves, golden.curves,
+        "{case}: fitted curves, {bless}"
+    );
+    for (name, value, bound) in [
+        ("hausdorff", actual.hausdorff, golden.hausdorff),
+        ("chamfer", actual.chamfer, golden.chamfer),
+        ("max curve rms", actual.max_curve_rms, golden.max_curve_rms),
+    ] {
+        assert!(
+            value <= bound + ERROR_SLACK,
+            "{case}: {name} went from {bound} to {value}, {bless}"
+        );
+    }
+    assert!(
+        actual.f1 >= golden.f1 - ERROR_SLACK,
+        "{case}: f1 went from {} to {}, {bless}",
+        golden.f1,
+        actual.f1
+    );
+    // the metrics are only held to the slack above, the hashes have to match exactly
+    for (name, value, expected) in [
+        ("edge map", &actual.edges_hash, &golden.edges_hash),
+        ("equations", &actual.equations_hash, &golden.equations_hash),
+        ("svg", &actual.svg_hash, &golden.svg_hash),
+        ("render", &actual.render_hash, &golden.render_hash),
+    ] {
+        assert_eq!(value, expected, "{case}: {name} changed, {bless}");
+    }
+}
+
+fn line_art() -> Pipeline {
+    config::preset("line-art").unwrap()
+}
+
+#[test]
+fn test_lines2() {
+    check("lines2", "lines2.png", line_art());
+}
+
+#[test]
+fn test_smile() {
+    check("smile", "smile.png", line_art());
+}
+
+#[test]
+fn test_checkmark() {
+    check("checkmark", "checkmark.png", line_art());
+}
+
+#[test]
+fn test_islands1() {
+    check("islands1", "islands1.png", line_art());
+}
+
+#[test]
+fn test_branches() {
+    check("branches", "branches.png", line_art());
+}
+
+#[test]
+fn test_shapes1_sobel() {
+    check("shapes1_sobel", "shapes1.png", Pipeline::new());
+}
+
+#[test]
+fn test_shapes1_photo() {
+    check(
+        "shapes1_photo",
+        "shapes1.png",
+        config::preset("photo").unwrap(),
+    );
+}
+
+#[test]
+fn test_toronto_sobel() {
+    check("toronto_sobel", "toronto.png", Pipeline::new());
+}